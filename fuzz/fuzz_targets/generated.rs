@@ -0,0 +1,29 @@
+//! Parse and compile programs generated from the fuzzer input, see
+//! `tests/fuzz.rs`.
+#![no_main]
+
+use {
+    libfuzzer_sys::fuzz_target,
+    olus::{
+        builtins::Builtin,
+        front::{ElementRef, Generator, Node, compile, parse},
+    },
+};
+
+fuzz_target!(|bytes: &[u8]| {
+    let source = Generator::from_bytes(bytes).program();
+    let root = parse(&source);
+    assert_eq!(root.text().to_string(), source);
+    assert!(
+        !has_errors(&root),
+        "Generated program does not parse:\n{source}"
+    );
+    let _ = compile(source, &root, Builtin::resolve);
+});
+
+fn has_errors(node: &Node) -> bool {
+    node.children_with_tokens().any(|element| match element {
+        ElementRef::Node(node) => has_errors(node),
+        ElementRef::Token(token) => token.kind().is_error(),
+    })
+}
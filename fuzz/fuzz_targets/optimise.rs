@@ -0,0 +1,18 @@
+//! Run programs generated from the fuzzer input before and after
+//! optimisation, see `tests/differential.rs`. Runtime errors abort, as the
+//! fuzzer turns panics into crashes, but generated programs have none.
+#![no_main]
+
+use {
+    libfuzzer_sys::fuzz_target,
+    olus::{differential::compare, front::Generator, ir::OptLevel},
+};
+
+fuzz_target!(|bytes: &[u8]| {
+    let source = Generator::from_bytes(bytes).runnable();
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        if let Some(difference) = compare(&source, level) {
+            panic!("Differs at {level:?}:\n{source}\n{difference}");
+        }
+    }
+});
@@ -0,0 +1,16 @@
+//! Parse and compile arbitrary text, see `tests/fuzz.rs`.
+#![no_main]
+
+use {
+    libfuzzer_sys::fuzz_target,
+    olus::{
+        builtins::Builtin,
+        front::{compile, parse},
+    },
+};
+
+fuzz_target!(|source: &str| {
+    let root = parse(source);
+    assert_eq!(root.text().to_string(), source);
+    let _ = compile(source.to_string(), &root, Builtin::resolve);
+});
@@ -1,15 +1,65 @@
 use {
     olus::{
-        Files,
-        front::{compile, parse, pretty_print_cst},
-        interpreter::{Value, evaluate},
-        ir::{Atom, Program, pretty_print_ir},
+        Diagnostic, Files,
+        builtins::{Builtin, Capabilities},
+        engine::{Engine, EngineError},
+        front::{compile, is_complete, parse, pretty_print_cst},
+        interpreter::{Value, evaluate, evaluate_lowered},
+        ir::{ControlFlow, OptLevel, PassManager, Program, pretty_print_ir, pretty_print_lowered},
+        repl::{Session, SessionError},
+        types::infer,
+    },
+    std::{
+        env,
+        fs::{self, OpenOptions},
+        io::{Write, stderr, stdin},
+        mem::take,
+        panic::{AssertUnwindSafe, catch_unwind},
+        path::PathBuf,
+        process,
+        rc::Rc,
     },
-    std::{mem::swap, path::PathBuf},
 };
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let path = PathBuf::from("./examples/test.olus");
+    let mut path = PathBuf::from("./examples/test.olus");
+    let mut level = OptLevel::default();
+    let mut stats = false;
+    let mut verify = cfg!(debug_assertions);
+    let mut lower = None;
+    let mut show_types = false;
+    let mut capabilities = Capabilities::default();
+    let mut arguments = Vec::new();
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("repl") => return repl(args.skip(1).collect()),
+        Some("test") => return test(args.skip(1).collect()),
+        _ => {}
+    }
+    while let Some(arg) = args.next() {
+        if let Some(l) = arg.strip_prefix("-O") {
+            level = l.parse()?;
+        } else if arg == "--stats" {
+            stats = true;
+        } else if arg == "--verify-ir" {
+            verify = true;
+        } else if arg == "--lower" {
+            lower = Some(false);
+        } else if arg == "--lift" {
+            lower = Some(true);
+        } else if arg == "--types" {
+            show_types = true;
+        } else if grant(&mut capabilities, &arg) {
+        } else {
+            // Remaining arguments are for the program.
+            path = PathBuf::from(&arg);
+            arguments.push(arg);
+            arguments.extend(&mut args);
+        }
+    }
+    if capabilities.arguments.is_some() {
+        capabilities.arguments = Some(arguments);
+    }
 
     let mut files = Files::new();
     let file_id = files.insert(path)?;
@@ -18,7 +68,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     pretty_print_cst(&root, 1);
 
-    let mut program = compile(source.to_string(), &root, builtin_resolve);
+    let mut program = match compile(source.to_string(), &root, Builtin::resolve) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in errors {
+                error.diagnostic(file_id).report().eprint(&files)?;
+            }
+            return Err("Compilation failed.".into());
+        }
+    };
 
     // Find a Prcocedure called main.
     let Some(main) = program.procedure_by_name("main") else {
@@ -26,67 +84,267 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     let main_id = main.id();
 
-    program.tree_shake(main_id);
-    pretty_print_ir(&program);
+    // `main` optionally takes the command-line arguments before `exit`.
+    let parameters = main.arguments.len() - 1;
+    if !matches!(parameters, 1 | 2) {
+        let source = main.name().source;
+        let diagnostic = Diagnostic {
+            message: format!(
+                "Procedure `main` takes {parameters} parameters, expected `main exit` or `main \
+                 arguments exit`."
+            ),
+            span:    file_id.span(source.start..source.end),
+        };
+        diagnostic.report().eprint(&files)?;
+        return Err("Invalid `main` procedure.".into());
+    }
+
+    // Type check the whole program, including unreachable procedures.
     program.closure_analysis();
+    let types = infer(&program);
+    for error in types.errors() {
+        error.diagnostic(file_id).report().eprint(&files)?;
+    }
+    if show_types {
+        for proc in &program.procedures {
+            if proc.name().named {
+                let name = program.string(proc.name().source);
+                eprintln!("{name} : {}", types.of(proc.id()).unwrap());
+            }
+        }
+    }
+    if !types.errors().is_empty() {
+        return Err("Type checking failed.".into());
+    }
+
+    let mut passes = PassManager::with_level(main_id, level).verify(verify);
+    passes.run(&mut program)?;
+    if stats {
+        for statistics in passes.statistics() {
+            eprintln!("{statistics}");
+        }
+    }
+    pretty_print_ir(&program);
+
+    // Report calls with the wrong number of arguments.
+    let flow = ControlFlow::analyse(&program, main_id, 1);
+    for mismatch in flow.arity_mismatches() {
+        mismatch.diagnostic(file_id).report().eprint(&files)?;
+    }
+
+    // Construct an initial call for the virtual machine. Without the
+    // `arguments` capability `main` receives an empty array.
+    let mut call = vec![Value::Closure(main_id, vec![])];
+    if parameters == 2 {
+        let arguments = capabilities.arguments.clone().unwrap_or_default();
+        let arguments = arguments.into_iter().map(Value::String).collect();
+        call.push(Value::Array(Rc::new(arguments)));
+    }
+    call.push(Value::Builtin(Builtin::Exit));
 
-    let main = program.procedure_by_id(main_id).unwrap();
-    let [main, _exit] = main.arguments.as_slice() else {
-        panic!("Procedure `main` should have one arguments.");
+    let status = if let Some(lift) = lower {
+        let lowered = program.closure_convert(main_id, lift);
+        pretty_print_lowered(&lowered);
+        evaluate_lowered(
+            &lowered,
+            |call| builtin(&capabilities, &program, call),
+            &call,
+        )
+    } else {
+        evaluate(
+            &program,
+            |program, call| builtin(&capabilities, program, call),
+            &call,
+        )
     };
+    process::exit(status)
+}
 
-    // Construct an initial call for the virtual machine.
-    evaluate(&program, builtin_eval, &[
-        Value::Closure(main.id, vec![]),
-        Value::Builtin("exit"),
-    ]);
+/// Command-line flags granting each capability, see [`grant`].
+const CAPABILITIES: &[(&str, &str)] = &[
+    ("--allow-stdin", "stdin"),
+    ("--allow-read", "read_files"),
+    ("--allow-write", "write_files"),
+    ("--allow-env", "environment"),
+    ("--allow-args", "arguments"),
+    ("--allow-clock", "clock"),
+];
 
-    Ok(())
+/// Grant the capability of a flag from [`CAPABILITIES`], or all of them for
+/// `--allow-all`. Returns whether `flag` is one of these. The arguments are
+/// filled in once they are known.
+fn grant(capabilities: &mut Capabilities, flag: &str) -> bool {
+    if flag == "--allow-all" {
+        *capabilities = Capabilities::all(Vec::new());
+        return true;
+    }
+    match CAPABILITIES
+        .iter()
+        .find(|(f, _)| *f == flag)
+        .map(|(_, c)| *c)
+    {
+        Some("stdin") => capabilities.stdin = true,
+        Some("read_files") => capabilities.read_files = true,
+        Some("write_files") => capabilities.write_files = true,
+        Some("environment") => capabilities.environment = true,
+        Some("arguments") => capabilities.arguments = Some(Vec::new()),
+        Some("clock") => capabilities.clock = true,
+        _ => return false,
+    }
+    true
 }
 
-fn builtin_resolve(name: &str) -> Option<&'static str> {
-    const BUILTINS: &[&str] = &["exit", "print", "add", "if", "is_zero", "sub", "mul"];
-    BUILTINS.iter().copied().find(|b| b == &name)
+/// Evaluate a builtin call, exiting with status 1 if it lacks a capability.
+fn builtin(
+    capabilities: &Capabilities,
+    program: &Program<Builtin>,
+    call: &mut Vec<Value<Builtin>>,
+) -> Option<i32> {
+    if let Value::Builtin(builtin) = call[0]
+        && let Some(capability) = capabilities.missing(builtin)
+    {
+        let flag = CAPABILITIES.iter().find(|(_, c)| *c == capability);
+        let flag = flag.map_or("--allow-all", |(f, _)| *f);
+        eprintln!("The `{builtin}` builtin requires the `{capability}` capability, see `{flag}`.");
+        return Some(1);
+    }
+    capabilities.evaluate(program, call)
 }
 
-fn builtin_eval(program: &Program<&str>, call: &mut Vec<Value<&str>>) -> Option<()> {
-    let Some(Value::Builtin(builtin)) = call.first() else {
-        panic!()
+/// Run the tests of a file, `olus test [--allow-…] path`, and exit with status
+/// 1 if any fail.
+fn test(arguments: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut capabilities = Capabilities::default();
+    let mut path = None;
+    for arg in arguments {
+        if !grant(&mut capabilities, &arg) {
+            path = Some(arg);
+        }
+    }
+    let Some(path) = path else {
+        return Err("Usage: olus test [--allow-…] <path>".into());
     };
-    let mut body = match *builtin {
-        "print" => {
-            println!("> {:?}", call[1]);
-            vec![call[2].clone()]
-        }
-        "exit" => {
-            println!("> Exit");
-            return Some(());
-        }
-        "add" => {
-            let Value::Number(a) = call[1] else { panic!() };
-            let Value::Number(b) = call[2] else { panic!() };
-            vec![call[3].clone(), Value::Number(a + b)]
-        }
-        "sub" => {
-            let Value::Number(a) = call[1] else { panic!() };
-            let Value::Number(b) = call[2] else { panic!() };
-            vec![call[3].clone(), Value::Number(a - b)]
-        }
-        "mul" => {
-            let Value::Number(a) = call[1] else { panic!() };
-            let Value::Number(b) = call[2] else { panic!() };
-            vec![call[3].clone(), Value::Number(a * b)]
-        }
-        "is_zero" => {
-            let Value::Number(a) = call[1] else { panic!() };
-            vec![call[2].clone(), Value::Number((a == 0).into())]
-        }
-        "if" => {
-            let Value::Number(a) = call[1] else { panic!() };
-            vec![(if a == 1 { &call[2] } else { &call[3] }).clone()]
-        }
-        _ => unimplemented!(),
+    let mut engine = Engine::new().capabilities(capabilities);
+    let module = match engine.load(PathBuf::from(path)) {
+        Ok(module) => module,
+        Err(EngineError::Compile(diagnostics)) => {
+            for diagnostic in diagnostics {
+                diagnostic.report().eprint(engine.files())?;
+            }
+            return Err("Compilation failed.".into());
+        }
+        Err(error) => return Err(error.into()),
     };
-    swap(call, &mut body);
-    None
+
+    let (mut passed, mut failed) = (0, 0);
+    for test in module.tests() {
+        // Runtime errors panic, the panic message is already printed.
+        let result = catch_unwind(AssertUnwindSafe(|| module.test(test)));
+        if matches!(result, Ok(Ok(()))) {
+            passed += 1;
+            println!("test “{}” ... ok", test.name);
+            continue;
+        }
+        failed += 1;
+        println!("test “{}” ... FAILED", test.name);
+        match result {
+            Ok(Err(EngineError::Assertion(assertion))) => {
+                let diagnostic = Diagnostic {
+                    message: assertion.to_string(),
+                    span:    assertion.span,
+                };
+                diagnostic.report().eprint(engine.files())?;
+            }
+            Ok(Err(error)) => eprintln!("{error}"),
+            _ => {}
+        }
+    }
+    println!("{passed} passed, {failed} failed");
+    if failed > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Read inputs from standard input until it ends or `:quit`. Inputs continue
+/// over several lines until they are complete. The history is kept in
+/// `$OLUS_HISTORY`, or `~/.olus_history`, one input per line.
+fn repl(arguments: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut capabilities = Capabilities::default();
+    for arg in arguments {
+        if !grant(&mut capabilities, &arg) {
+            return Err("Usage: olus repl [--allow-…]".into());
+        }
+    }
+    let history_path = env::var_os("OLUS_HISTORY")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".olus_history")));
+    let history = history_path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|text| text.lines().map(unescape).collect())
+        .unwrap_or_default();
+    let mut history_file =
+        history_path.and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
+    let mut session = Session::new(capabilities).with_history(history);
+    let mut input = String::new();
+    loop {
+        eprint!("{}", if input.is_empty() { "> " } else { ". " });
+        stderr().flush()?;
+        if stdin().read_line(&mut input)? == 0 {
+            return Ok(());
+        }
+        if input.trim() == ":quit" {
+            return Ok(());
+        }
+        if input.trim().is_empty() {
+            input.clear();
+            continue;
+        }
+        if !input.starts_with(':') && !is_complete(&input) {
+            continue;
+        }
+        let text = take(&mut input);
+        if let Some(file) = &mut history_file {
+            writeln!(file, "{}", escape(text.trim_end()))?;
+        }
+
+        // Runtime errors panic, the panic message is already printed.
+        match catch_unwind(AssertUnwindSafe(|| session.input(&text))) {
+            Ok(Ok(output)) if output.is_empty() => {}
+            Ok(Ok(output)) => println!("{output}"),
+            Ok(Err(SessionError::Engine(EngineError::Compile(diagnostics)))) => {
+                for diagnostic in diagnostics {
+                    diagnostic.report().eprint(session.files())?;
+                }
+            }
+            Ok(Err(error)) => eprintln!("{error}"),
+            Err(_) => {}
+        }
+    }
+}
+
+/// An input on a single line of the history file.
+fn escape(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// The input of a line of the history file, see [`escape`].
+fn unescape(line: &str) -> String {
+    let mut input = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                input.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                input.push('\\');
+                chars.next();
+            }
+            _ => input.push(c),
+        }
+    }
+    input
 }
@@ -0,0 +1,957 @@
+//! Builtin procedures available to Oluś programs.
+//!
+//! Builtins that interact with the host, such as reading files, require a
+//! capability that the embedder grants through [`Capabilities`].
+
+use {
+    crate::{
+        front::Span,
+        interpreter::{Control, Key, Operator, Value},
+        ir::{Atom, Flow, Flowing, Fold, Number, Program},
+        types::{Type, Typed},
+    },
+    core::{
+        fmt::{self, Display},
+        ops::ControlFlow::{self, Break, Continue},
+    },
+    num_traits::ToPrimitive,
+    std::{
+        collections::HashMap,
+        env, fs,
+        io::stdin,
+        mem::swap,
+        rc::Rc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Builtin {
+    Exit,
+    Print,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    IsZero,
+    If,
+    Concat,
+    Length,
+    Slice,
+    Compare,
+    Format,
+    Parse,
+    Show,
+    Array,
+    ArrayPush,
+    ArrayGet,
+    ArraySet,
+    ArrayLength,
+    Map,
+    MapGet,
+    MapSet,
+    MapRemove,
+    MapKeys,
+    MapLength,
+    ReadLine,
+    ReadFile,
+    WriteFile,
+    EnvVar,
+    Arguments,
+    Clock,
+    Handle,
+    Perform,
+    Spawn,
+    Yield,
+    Channel,
+    Send,
+    Recv,
+    Join,
+    AssertEq,
+    AssertNe,
+    /// Body of procedures that are never called, see
+    /// [`crate::ir::Program::devirtualize`]. Calling it is a runtime error.
+    Unreachable,
+    /// A function provided by the embedder, see
+    /// [`crate::engine::Engine::function`].
+    Host {
+        index: usize,
+        arity: usize,
+    },
+}
+
+/// Host capabilities granted to a program. The default grants none, so an
+/// untrusted program can only compute and `print`.
+#[derive(Clone, Default, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Capabilities {
+    /// Read standard input with `read_line`.
+    pub stdin:       bool,
+    /// Read files with `read_file`.
+    pub read_files:  bool,
+    /// Write files with `write_file`.
+    pub write_files: bool,
+    /// Read environment variables with `env_var`.
+    pub environment: bool,
+    /// Command-line arguments returned by `arguments`.
+    pub arguments:   Option<Vec<String>>,
+    /// Read the system time with `clock`.
+    pub clock:       bool,
+}
+
+impl Builtin {
+    pub const ALL: &[Self] = &[
+        Self::Exit,
+        Self::Print,
+        Self::Add,
+        Self::Sub,
+        Self::Mul,
+        Self::Div,
+        Self::Rem,
+        Self::IsZero,
+        Self::If,
+        Self::Concat,
+        Self::Length,
+        Self::Slice,
+        Self::Compare,
+        Self::Format,
+        Self::Parse,
+        Self::Show,
+        Self::Array,
+        Self::ArrayPush,
+        Self::ArrayGet,
+        Self::ArraySet,
+        Self::ArrayLength,
+        Self::Map,
+        Self::MapGet,
+        Self::MapSet,
+        Self::MapRemove,
+        Self::MapKeys,
+        Self::MapLength,
+        Self::ReadLine,
+        Self::ReadFile,
+        Self::WriteFile,
+        Self::EnvVar,
+        Self::Arguments,
+        Self::Clock,
+        Self::Handle,
+        Self::Perform,
+        Self::Spawn,
+        Self::Yield,
+        Self::Channel,
+        Self::Send,
+        Self::Recv,
+        Self::Join,
+        Self::AssertEq,
+        Self::AssertNe,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Exit => "exit",
+            Self::Print => "print",
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Rem => "rem",
+            Self::IsZero => "is_zero",
+            Self::If => "if",
+            Self::Concat => "concat",
+            Self::Length => "length",
+            Self::Slice => "slice",
+            Self::Compare => "compare",
+            Self::Format => "format",
+            Self::Parse => "parse",
+            Self::Show => "show",
+            Self::Array => "array",
+            Self::ArrayPush => "array_push",
+            Self::ArrayGet => "array_get",
+            Self::ArraySet => "array_set",
+            Self::ArrayLength => "array_length",
+            Self::Map => "map",
+            Self::MapGet => "map_get",
+            Self::MapSet => "map_set",
+            Self::MapRemove => "map_remove",
+            Self::MapKeys => "map_keys",
+            Self::MapLength => "map_length",
+            Self::ReadLine => "read_line",
+            Self::ReadFile => "read_file",
+            Self::WriteFile => "write_file",
+            Self::EnvVar => "env_var",
+            Self::Arguments => "arguments",
+            Self::Clock => "clock",
+            Self::Handle => "handle",
+            Self::Perform => "perform",
+            Self::Spawn => "spawn",
+            Self::Yield => "yield",
+            Self::Channel => "channel",
+            Self::Send => "send",
+            Self::Recv => "recv",
+            Self::Join => "join",
+            Self::AssertEq => "assert_eq",
+            Self::AssertNe => "assert_ne",
+            Self::Unreachable => "unreachable",
+            Self::Host { .. } => "host",
+        }
+    }
+
+    /// Apply a binary arithmetic builtin. See [`Number`] for the semantics.
+    fn arithmetic(self, a: &Number, b: &Number) -> Number {
+        match self {
+            Self::Add => a.add(b),
+            Self::Sub => a.sub(b),
+            Self::Mul => a.mul(b),
+            Self::Div => a.div(b),
+            Self::Rem => a.rem(b),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Whether `assert_eq` or `assert_ne` holds for `actual` and `expected`.
+    #[must_use]
+    pub fn assertion(self, actual: &Value<Self>, expected: &Value<Self>) -> bool {
+        match self {
+            Self::AssertEq => actual == expected,
+            Self::AssertNe => actual != expected,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resolve a builtin by name, for use with [`crate::front::compile`].
+    #[must_use]
+    pub fn resolve(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|b| b.name() == name)
+    }
+
+    /// Decode a builtin from its [`Display`] text, for use with
+    /// [`crate::interpreter::Scheduler::resume`]. Unlike [`Builtin::resolve`]
+    /// this includes `unreachable` and host functions, `host index/arity`.
+    #[must_use]
+    pub fn decode(text: &str) -> Option<Self> {
+        if text == Self::Unreachable.name() {
+            return Some(Self::Unreachable);
+        }
+        if let Some((index, arity)) = text
+            .strip_prefix("host ")
+            .and_then(|host| host.split_once('/'))
+        {
+            return Some(Self::Host {
+                index: index.parse().ok()?,
+                arity: arity.parse().ok()?,
+            });
+        }
+        Self::resolve(text)
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Host { index, arity } => write!(f, "host {index}/{arity}"),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+impl Fold for Builtin {
+    fn is_pure(&self) -> bool {
+        !matches!(
+            self,
+            Self::Exit
+                | Self::Print
+                | Self::ReadLine
+                | Self::ReadFile
+                | Self::WriteFile
+                | Self::EnvVar
+                | Self::Arguments
+                | Self::Clock
+                | Self::Handle
+                | Self::Perform
+                | Self::Spawn
+                | Self::Yield
+                | Self::Channel
+                | Self::Send
+                | Self::Recv
+                | Self::Join
+                | Self::AssertEq
+                | Self::AssertNe
+                | Self::Unreachable
+                | Self::Host { .. }
+        )
+    }
+
+    fn fold(&self, source: Span, arguments: &[Atom<Self>]) -> Option<Vec<Atom<Self>>> {
+        let number = |value| Atom::Number { source, value };
+        let string = |value| Atom::String { source, value };
+        match (self, arguments) {
+            (
+                Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Rem,
+                [
+                    Atom::Number { value: a, .. },
+                    Atom::Number { value: b, .. },
+                    ret,
+                ],
+            ) => Some(vec![ret.clone(), number(self.arithmetic(a, b))]),
+            (Self::IsZero, [Atom::Number { value, .. }, ret]) => {
+                Some(vec![ret.clone(), number(value.is_zero().into())])
+            }
+            (Self::If, [Atom::Number { value, .. }, then, other]) => Some(vec![
+                (if *value == Number::from(true) {
+                    then
+                } else {
+                    other
+                })
+                .clone(),
+            ]),
+            (
+                Self::Concat,
+                [
+                    Atom::String { value: a, .. },
+                    Atom::String { value: b, .. },
+                    ret,
+                ],
+            ) => Some(vec![ret.clone(), string(format!("{a}{b}"))]),
+            (Self::Length, [Atom::String { value, .. }, ret]) => {
+                Some(vec![ret.clone(), number(length(value))])
+            }
+            (
+                Self::Slice,
+                [
+                    Atom::String { value, .. },
+                    Atom::Number { value: start, .. },
+                    Atom::Number { value: end, .. },
+                    ret,
+                ],
+            ) => Some(vec![ret.clone(), string(slice(value, start, end))]),
+            (
+                Self::Compare,
+                [
+                    Atom::String { value: a, .. },
+                    Atom::String { value: b, .. },
+                    ret,
+                ],
+            ) => Some(vec![ret.clone(), number(compare(a, b))]),
+            (Self::Format | Self::Show, [Atom::Number { value, .. }, ret]) => {
+                Some(vec![ret.clone(), string(value.to_string())])
+            }
+            (Self::Show, [Atom::String { value, .. }, ret]) => {
+                Some(vec![ret.clone(), string(value.clone())])
+            }
+            (Self::Parse, [Atom::String { value, .. }, ret, fail]) => Some(
+                parse(value).map_or_else(|| vec![fail.clone()], |n| vec![ret.clone(), number(n)]),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl Control for Builtin {
+    fn control(&self) -> Option<Operator> {
+        match self {
+            Self::Handle => Some(Operator::Handle),
+            Self::Perform => Some(Operator::Perform),
+            Self::Spawn => Some(Operator::Spawn),
+            Self::Yield => Some(Operator::Yield),
+            Self::Channel => Some(Operator::Channel),
+            Self::Send => Some(Operator::Send),
+            Self::Recv => Some(Operator::Receive),
+            Self::Join => Some(Operator::Join),
+            _ => None,
+        }
+    }
+}
+
+impl Flow for Builtin {
+    fn arity(&self) -> usize {
+        match self {
+            Self::Unreachable => 0,
+            Self::Exit
+            | Self::Array
+            | Self::Map
+            | Self::Arguments
+            | Self::Clock
+            | Self::Yield
+            | Self::Channel => 1,
+            Self::ReadLine
+            | Self::Print
+            | Self::IsZero
+            | Self::Length
+            | Self::Format
+            | Self::Show
+            | Self::ArrayLength
+            | Self::MapKeys
+            | Self::MapLength
+            | Self::Perform
+            | Self::Spawn
+            | Self::Recv
+            | Self::Join => 2,
+            Self::Add
+            | Self::Sub
+            | Self::Mul
+            | Self::Div
+            | Self::Rem
+            | Self::If
+            | Self::Concat
+            | Self::Compare
+            | Self::Parse
+            | Self::ArrayPush
+            | Self::MapRemove
+            | Self::ReadFile
+            | Self::EnvVar
+            | Self::Handle
+            | Self::Send
+            | Self::AssertEq
+            | Self::AssertNe => 3,
+            Self::Slice | Self::ArrayGet | Self::MapGet | Self::MapSet | Self::WriteFile => 4,
+            Self::ArraySet => 5,
+            Self::Host { arity, .. } => *arity,
+        }
+    }
+
+    fn flow(&self) -> Vec<(usize, Vec<Flowing>)> {
+        match self {
+            Self::Exit | Self::Perform | Self::Unreachable | Self::Host { .. } => vec![],
+            Self::Print => vec![(1, vec![])],
+            Self::Add
+            | Self::Sub
+            | Self::Mul
+            | Self::Div
+            | Self::Rem
+            | Self::Concat
+            | Self::Compare
+            | Self::ArrayPush
+            | Self::MapRemove => vec![(2, vec![Flowing::Opaque])],
+            Self::IsZero
+            | Self::Length
+            | Self::Format
+            | Self::Show
+            | Self::ArrayLength
+            | Self::MapKeys
+            | Self::MapLength => vec![(1, vec![Flowing::Opaque])],
+            Self::If => vec![(1, vec![]), (2, vec![])],
+            Self::Slice | Self::MapSet => vec![(3, vec![Flowing::Opaque])],
+            Self::Parse | Self::EnvVar => vec![(1, vec![Flowing::Opaque]), (2, vec![])],
+            Self::Array | Self::Map | Self::Arguments | Self::Clock | Self::Channel => {
+                vec![(0, vec![Flowing::Opaque])]
+            }
+            Self::Spawn => vec![(0, vec![Flowing::Unknown]), (1, vec![Flowing::Opaque])],
+            Self::Yield => vec![(0, vec![])],
+            Self::Send | Self::AssertEq | Self::AssertNe => vec![(2, vec![])],
+            Self::Recv | Self::Join => vec![(1, vec![Flowing::Unknown])],
+            Self::ReadLine => vec![(0, vec![Flowing::Opaque]), (1, vec![])],
+            Self::ReadFile => vec![(1, vec![Flowing::Opaque]), (2, vec![Flowing::Opaque])],
+            Self::WriteFile => vec![(2, vec![]), (3, vec![Flowing::Opaque])],
+            Self::ArrayGet | Self::MapGet => vec![(2, vec![Flowing::Unknown]), (3, vec![])],
+            Self::ArraySet => vec![(3, vec![Flowing::Opaque]), (4, vec![])],
+            Self::Handle => vec![
+                (0, vec![Flowing::Unknown]),
+                (1, vec![
+                    Flowing::Unknown,
+                    Flowing::Unknown,
+                    Flowing::Unknown,
+                ]),
+            ],
+        }
+    }
+
+    /// Elements stored in a container can come back out of it as
+    /// [`Flowing::Unknown`], so they escape.
+    fn escaping(&self) -> Vec<usize> {
+        match self {
+            Self::ArrayPush | Self::Send => vec![1],
+            Self::ArraySet | Self::MapSet | Self::Handle => vec![2],
+            Self::Perform => vec![0, 1],
+            Self::Host { arity, .. } => (0..*arity).collect(),
+            _ => vec![],
+        }
+    }
+
+    fn unreachable() -> Self {
+        Self::Unreachable
+    }
+}
+
+impl Typed for Builtin {
+    fn signature(&self) -> Type {
+        use Type::{Number, Procedure, String, Variable};
+        let array = || Type::Array(Box::new(Variable(0)));
+        let map = || Type::Map(Box::new(Variable(0)), Box::new(Variable(1)));
+        match self {
+            Self::Exit => Procedure(vec![Number]),
+            Self::Print => Procedure(vec![Variable(0), Procedure(vec![])]),
+            Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Rem => {
+                Procedure(vec![Number, Number, Procedure(vec![Number])])
+            }
+            Self::IsZero => Procedure(vec![Number, Procedure(vec![Number])]),
+            Self::If => Procedure(vec![Number, Procedure(vec![]), Procedure(vec![])]),
+            Self::Concat => Procedure(vec![String, String, Procedure(vec![String])]),
+            Self::Length => Procedure(vec![String, Procedure(vec![Number])]),
+            Self::Slice => Procedure(vec![String, Number, Number, Procedure(vec![String])]),
+            Self::Compare => Procedure(vec![String, String, Procedure(vec![Number])]),
+            Self::Format => Procedure(vec![Number, Procedure(vec![String])]),
+            Self::Show => Procedure(vec![Variable(0), Procedure(vec![String])]),
+            Self::Parse => Procedure(vec![String, Procedure(vec![Number]), Procedure(vec![])]),
+            Self::Array => Procedure(vec![Procedure(vec![array()])]),
+            Self::ArrayPush => Procedure(vec![array(), Variable(0), Procedure(vec![array()])]),
+            Self::ArrayGet => Procedure(vec![
+                array(),
+                Number,
+                Procedure(vec![Variable(0)]),
+                Procedure(vec![]),
+            ]),
+            Self::ArraySet => Procedure(vec![
+                array(),
+                Number,
+                Variable(0),
+                Procedure(vec![array()]),
+                Procedure(vec![]),
+            ]),
+            Self::ArrayLength => Procedure(vec![array(), Procedure(vec![Number])]),
+            Self::Map => Procedure(vec![Procedure(vec![map()])]),
+            Self::MapGet => Procedure(vec![
+                map(),
+                Variable(0),
+                Procedure(vec![Variable(1)]),
+                Procedure(vec![]),
+            ]),
+            Self::MapSet => Procedure(vec![
+                map(),
+                Variable(0),
+                Variable(1),
+                Procedure(vec![map()]),
+            ]),
+            Self::MapRemove => Procedure(vec![map(), Variable(0), Procedure(vec![map()])]),
+            Self::MapKeys => Procedure(vec![
+                map(),
+                Procedure(vec![Type::Array(Box::new(Variable(0)))]),
+            ]),
+            Self::MapLength => Procedure(vec![map(), Procedure(vec![Number])]),
+            Self::ReadLine => Procedure(vec![Procedure(vec![String]), Procedure(vec![])]),
+            Self::ReadFile => Procedure(vec![
+                String,
+                Procedure(vec![String]),
+                Procedure(vec![String]),
+            ]),
+            Self::WriteFile => Procedure(vec![
+                String,
+                String,
+                Procedure(vec![]),
+                Procedure(vec![String]),
+            ]),
+            Self::EnvVar => Procedure(vec![String, Procedure(vec![String]), Procedure(vec![])]),
+            Self::Arguments => Procedure(vec![Procedure(vec![Type::Array(Box::new(String))])]),
+            Self::Clock => Procedure(vec![Procedure(vec![Number])]),
+            Self::Handle => {
+                let ret = || Procedure(vec![Variable(0)]);
+                Procedure(vec![
+                    Procedure(vec![ret()]),
+                    Procedure(vec![Variable(1), Variable(2), ret()]),
+                    ret(),
+                ])
+            }
+            Self::Perform => Procedure(vec![Variable(0), Variable(1)]),
+            Self::Spawn => Procedure(vec![
+                Procedure(vec![Procedure(vec![Variable(0)])]),
+                Procedure(vec![Variable(1)]),
+            ]),
+            Self::Yield => Procedure(vec![Procedure(vec![])]),
+            Self::Channel => Procedure(vec![Procedure(vec![Variable(0)])]),
+            Self::Send => Procedure(vec![Variable(0), Variable(1), Procedure(vec![])]),
+            Self::Recv | Self::Join => Procedure(vec![Variable(0), Procedure(vec![Variable(1)])]),
+            Self::AssertEq | Self::AssertNe => {
+                Procedure(vec![Variable(0), Variable(0), Procedure(vec![])])
+            }
+            Self::Unreachable => Procedure(vec![]),
+            Self::Host { .. } => Variable(0),
+        }
+    }
+}
+
+/// Evaluate a builtin call without capabilities, for use with
+/// [`crate::interpreter::evaluate`]. Evaluation ends with the status passed
+/// to `exit`.
+pub fn evaluate(program: &Program<Builtin>, call: &mut Vec<Value<Builtin>>) -> Option<i32> {
+    Capabilities::default().evaluate(program, call)
+}
+
+impl Capabilities {
+    /// Grant all capabilities, with the given command-line arguments.
+    #[must_use]
+    pub const fn all(arguments: Vec<String>) -> Self {
+        Self {
+            stdin:       true,
+            read_files:  true,
+            write_files: true,
+            environment: true,
+            arguments:   Some(arguments),
+            clock:       true,
+        }
+    }
+
+    /// Evaluate a builtin call with these capabilities, for use with
+    /// [`crate::interpreter::evaluate`].
+    ///
+    /// # Panics
+    ///
+    /// Panics on a type error or when a builtin needs a capability that was
+    /// not granted. Check [`Capabilities::missing`] first to report it
+    /// instead, like [`crate::engine::Module`] does.
+    pub fn evaluate(
+        &self,
+        _program: &Program<Builtin>,
+        call: &mut Vec<Value<Builtin>>,
+    ) -> Option<i32> {
+        let Some(Value::Builtin(builtin)) = call.first() else {
+            panic!()
+        };
+        match evaluate_call(self, *builtin, call) {
+            Continue(mut body) => {
+                swap(call, &mut body);
+                None
+            }
+            Break(status) => Some(status),
+        }
+    }
+
+    /// The capability `builtin` needs if it was not granted.
+    #[must_use]
+    pub const fn missing(&self, builtin: Builtin) -> Option<&'static str> {
+        let (capability, granted) = match builtin {
+            Builtin::ReadLine => ("stdin", self.stdin),
+            Builtin::ReadFile => ("read_files", self.read_files),
+            Builtin::WriteFile => ("write_files", self.write_files),
+            Builtin::EnvVar => ("environment", self.environment),
+            Builtin::Arguments => ("arguments", self.arguments.is_some()),
+            Builtin::Clock => ("clock", self.clock),
+            _ => return None,
+        };
+        if granted { None } else { Some(capability) }
+    }
+
+    /// Evaluate a call to a host builtin, checking its capability.
+    fn host(&self, builtin: Builtin, call: &[Value<Builtin>]) -> Vec<Value<Builtin>> {
+        if let Some(capability) = self.missing(builtin) {
+            panic!("The `{builtin}` builtin requires the `{capability}` capability.");
+        }
+        match builtin {
+            Builtin::ReadLine => {
+                let mut line = String::new();
+                match stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => vec![call[2].clone()],
+                    Ok(_) => {
+                        let length = line.trim_end_matches(['\n', '\r']).len();
+                        line.truncate(length);
+                        vec![call[1].clone(), Value::String(line)]
+                    }
+                }
+            }
+            Builtin::ReadFile => {
+                let Value::String(path) = &call[1] else {
+                    panic!()
+                };
+                fs::read_to_string(path).map_or_else(
+                    |error| vec![call[3].clone(), Value::String(error.to_string())],
+                    |text| vec![call[2].clone(), Value::String(text)],
+                )
+            }
+            Builtin::WriteFile => {
+                let Value::String(path) = &call[1] else {
+                    panic!()
+                };
+                let Value::String(text) = &call[2] else {
+                    panic!()
+                };
+                fs::write(path, text).map_or_else(
+                    |error| vec![call[4].clone(), Value::String(error.to_string())],
+                    |()| vec![call[3].clone()],
+                )
+            }
+            Builtin::EnvVar => {
+                let Value::String(name) = &call[1] else {
+                    panic!()
+                };
+                env::var(name).map_or_else(
+                    |_| vec![call[3].clone()],
+                    |value| vec![call[2].clone(), Value::String(value)],
+                )
+            }
+            Builtin::Arguments => {
+                let arguments = self.arguments.iter().flatten().cloned();
+                let arguments = arguments.map(Value::String).collect();
+                vec![call[1].clone(), Value::Array(Rc::new(arguments))]
+            }
+            Builtin::Clock => {
+                let seconds = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0.0, |time| time.as_secs_f64());
+                vec![call[1].clone(), Value::Number(seconds.into())]
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The next call after a builtin call, or the exit status if the program
+/// exits.
+#[allow(clippy::too_many_lines)]
+fn evaluate_call(
+    capabilities: &Capabilities,
+    builtin: Builtin,
+    call: &[Value<Builtin>],
+) -> ControlFlow<i32, Vec<Value<Builtin>>> {
+    Continue(match builtin {
+        Builtin::Print => {
+            match &call[1] {
+                Value::String(s) => println!("> {s}"),
+                value => println!("> {value}"),
+            }
+            vec![call[2].clone()]
+        }
+        Builtin::Exit => {
+            let [_, Value::Number(code)] = call else {
+                panic!("`exit` takes a number status.");
+            };
+            return Break(exit_status(code));
+        }
+        Builtin::Add | Builtin::Sub | Builtin::Mul | Builtin::Div | Builtin::Rem => {
+            let Value::Number(a) = &call[1] else { panic!() };
+            let Value::Number(b) = &call[2] else { panic!() };
+            vec![call[3].clone(), Value::Number(builtin.arithmetic(a, b))]
+        }
+        Builtin::IsZero => {
+            let Value::Number(a) = &call[1] else { panic!() };
+            vec![call[2].clone(), Value::Number(a.is_zero().into())]
+        }
+        Builtin::If => {
+            let Value::Number(a) = &call[1] else { panic!() };
+            vec![
+                (if *a == Number::from(true) {
+                    &call[2]
+                } else {
+                    &call[3]
+                })
+                .clone(),
+            ]
+        }
+        Builtin::Concat => {
+            let Value::String(a) = &call[1] else { panic!() };
+            let Value::String(b) = &call[2] else { panic!() };
+            vec![call[3].clone(), Value::String(format!("{a}{b}"))]
+        }
+        Builtin::Length => {
+            let Value::String(a) = &call[1] else { panic!() };
+            vec![call[2].clone(), Value::Number(length(a))]
+        }
+        Builtin::Slice => {
+            let Value::String(a) = &call[1] else { panic!() };
+            let Value::Number(start) = &call[2] else {
+                panic!()
+            };
+            let Value::Number(end) = &call[3] else {
+                panic!()
+            };
+            vec![call[4].clone(), Value::String(slice(a, start, end))]
+        }
+        Builtin::Compare => {
+            let Value::String(a) = &call[1] else { panic!() };
+            let Value::String(b) = &call[2] else { panic!() };
+            vec![call[3].clone(), Value::Number(compare(a, b))]
+        }
+        Builtin::Format => {
+            let Value::Number(a) = &call[1] else { panic!() };
+            vec![call[2].clone(), Value::String(a.to_string())]
+        }
+        Builtin::Show => {
+            let text = match &call[1] {
+                Value::String(a) => a.clone(),
+                Value::Number(a) => a.to_string(),
+                other => other.to_string(),
+            };
+            vec![call[2].clone(), Value::String(text)]
+        }
+        Builtin::Parse => {
+            let Value::String(a) = &call[1] else { panic!() };
+            parse(a).map_or_else(
+                || vec![call[3].clone()],
+                |n| vec![call[2].clone(), Value::Number(n)],
+            )
+        }
+        Builtin::Array => vec![call[1].clone(), Value::Array(Rc::default())],
+        Builtin::ArrayPush => {
+            let Value::Array(a) = &call[1] else { panic!() };
+            let mut a = a.clone();
+            Rc::make_mut(&mut a).push(call[2].clone());
+            vec![call[3].clone(), Value::Array(a)]
+        }
+        Builtin::ArrayGet => {
+            let Value::Array(a) = &call[1] else { panic!() };
+            let Value::Number(i) = &call[2] else { panic!() };
+            index(a, i).map_or_else(
+                || vec![call[4].clone()],
+                |i| vec![call[3].clone(), a[i].clone()],
+            )
+        }
+        Builtin::ArraySet => {
+            let Value::Array(a) = &call[1] else { panic!() };
+            let Value::Number(i) = &call[2] else { panic!() };
+            index(a, i).map_or_else(
+                || vec![call[5].clone()],
+                |i| {
+                    let mut a = a.clone();
+                    Rc::make_mut(&mut a)[i] = call[3].clone();
+                    vec![call[4].clone(), Value::Array(a)]
+                },
+            )
+        }
+        Builtin::ArrayLength => {
+            let Value::Array(a) = &call[1] else { panic!() };
+            vec![call[2].clone(), Value::Number(Number::from(a.len() as u64))]
+        }
+        Builtin::Map => vec![call[1].clone(), Value::Map(Rc::default())],
+        Builtin::MapGet => {
+            let Value::Map(m) = &call[1] else { panic!() };
+            let key = call[2].key().expect("Map key is a number or string.");
+            m.get(&key).map_or_else(
+                || vec![call[4].clone()],
+                |value| vec![call[3].clone(), value.clone()],
+            )
+        }
+        Builtin::MapSet => {
+            let Value::Map(m) = &call[1] else { panic!() };
+            let key = call[2].key().expect("Map key is a number or string.");
+            let mut m = m.clone();
+            Rc::make_mut(&mut m).insert(key, call[3].clone());
+            vec![call[4].clone(), Value::Map(m)]
+        }
+        Builtin::MapRemove => {
+            let Value::Map(m) = &call[1] else { panic!() };
+            let key = call[2].key().expect("Map key is a number or string.");
+            let mut m = m.clone();
+            Rc::make_mut(&mut m).remove(&key);
+            vec![call[3].clone(), Value::Map(m)]
+        }
+        Builtin::MapKeys => {
+            let Value::Map(m) = &call[1] else { panic!() };
+            vec![call[2].clone(), Value::Array(Rc::new(keys(m)))]
+        }
+        Builtin::MapLength => {
+            let Value::Map(m) = &call[1] else { panic!() };
+            vec![call[2].clone(), Value::Number(Number::from(m.len() as u64))]
+        }
+        Builtin::ReadLine
+        | Builtin::ReadFile
+        | Builtin::WriteFile
+        | Builtin::EnvVar
+        | Builtin::Arguments
+        | Builtin::Clock => capabilities.host(builtin, call),
+        Builtin::Handle
+        | Builtin::Perform
+        | Builtin::Spawn
+        | Builtin::Yield
+        | Builtin::Channel
+        | Builtin::Send
+        | Builtin::Recv
+        | Builtin::Join => {
+            unreachable!("Control operators are evaluated by the interpreter.")
+        }
+        Builtin::AssertEq | Builtin::AssertNe => {
+            assert!(
+                call.len() == 4,
+                "`{builtin}` takes two values and a continuation."
+            );
+            assert!(
+                builtin.assertion(&call[1], &call[2]),
+                "{}",
+                assertion_message(builtin, &call[2], &call[1])
+            );
+            vec![call[3].clone()]
+        }
+        Builtin::Unreachable => panic!("Called a procedure that is never called."),
+        Builtin::Host { .. } => unreachable!("Host functions are evaluated by the engine."),
+    })
+}
+
+/// Explanation of a failed `assert_eq` or `assert_ne`.
+#[must_use]
+pub fn assertion_message(
+    builtin: Builtin,
+    expected: &Value<Builtin>,
+    actual: &Value<Builtin>,
+) -> String {
+    match builtin {
+        Builtin::AssertEq => format!("assertion failed: expected {expected}, got {actual}"),
+        _ => format!("assertion failed: expected a value other than {expected}"),
+    }
+}
+
+/// Length in Unicode scalar values.
+fn length(text: &str) -> Number {
+    Number::from(text.chars().count() as u64)
+}
+
+/// Characters `start..end`, with both ends clamped to the string. An empty
+/// range gives the empty string.
+fn slice(text: &str, start: &Number, end: &Number) -> String {
+    let end = end.to_index();
+    text.chars().take(end).skip(start.to_index()).collect()
+}
+
+/// Lexicographic comparison by Unicode scalar value: `-1`, `0` or `1`.
+fn compare(a: &str, b: &str) -> Number {
+    Number::from(a.cmp(b) as i64)
+}
+
+/// Parses a number literal, ignoring surrounding whitespace.
+fn parse(text: &str) -> Option<Number> {
+    text.trim().parse().ok()
+}
+
+/// Index into an array, if it is a whole number in range.
+fn index<T>(array: &[T], index: &Number) -> Option<usize> {
+    match index {
+        Number::Integer(i) => i.to_usize().filter(|i| *i < array.len()),
+        Number::Float(_) => None,
+    }
+}
+
+/// Keys of a map in ascending order.
+fn keys(map: &HashMap<Key, Value<Builtin>>) -> Vec<Value<Builtin>> {
+    let mut keys = map.keys().cloned().collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.into_iter().map(Value::from).collect()
+}
+
+/// Process exit status for an `exit` code. Codes that are not an `i32`,
+/// including floats, are a generic failure.
+fn exit_status(code: &Number) -> i32 {
+    match code {
+        Number::Integer(code) => code.to_i32().unwrap_or(1),
+        Number::Float(_) => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::status;
+
+    #[test]
+    fn exit() {
+        assert_eq!(status("main exit: exit 3\n"), 3);
+        assert_eq!(status("main exit: exit 1.5\n"), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "`exit` takes a number status.")]
+    fn exit_without_status() {
+        status("main exit: exit\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "`exit` takes a number status.")]
+    fn exit_with_string() {
+        status("main exit: exit “x”\n");
+    }
+}
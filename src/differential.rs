@@ -0,0 +1,254 @@
+//! Differential testing of the optimisation passes.
+//!
+//! A program is run before and after optimisation and the two runs are
+//! compared: the calls of impure builtins like `print`, in order, and how the
+//! run ended. Closures are left out of the calls, as their procedures differ
+//! between the programs.
+//!
+//! ```ignore
+//! if let Some(difference) = compare(source, OptLevel::O2) {
+//!     let reduced = reduce(source, |source| compare(source, OptLevel::O2).is_some());
+//!     panic!("{difference}\n{reduced}");
+//! }
+//! ```
+
+use {
+    crate::{
+        builtins::{Builtin, Capabilities},
+        front::{ElementRef, Kind, Node, NodeExt, Span, TokenExt, compile, parse},
+        interpreter::{Scheduler, Value, iterate},
+        ir::{Fold, OptLevel, PassError, PassManager, Program},
+    },
+    core::fmt::{self, Display},
+    std::{
+        any::Any,
+        panic::{AssertUnwindSafe, catch_unwind},
+    },
+};
+
+/// Steps after which a run is stopped.
+pub const STEPS: usize = 100_000;
+
+/// What a run did.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Run {
+    /// Calls of impure builtins other than `exit`.
+    pub effects: Vec<String>,
+    pub outcome: Outcome,
+}
+
+/// How a run ended.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// The program called `exit` with this status.
+    Exit(i32),
+    /// A runtime error, with its message.
+    Error(String),
+    /// The program did not finish in [`STEPS`] steps.
+    Timeout,
+}
+
+/// How the optimised program differs from the original.
+#[derive(Clone, Debug)]
+pub enum Difference {
+    /// A pass produced an invalid program.
+    Pass(PassError),
+    /// The runs differ.
+    Runs { original: Run, optimised: Run },
+}
+
+/// Run `main exit` of `source` with and without the optimisations of `level`
+/// and return how the runs differ.
+///
+/// Programs that do not compile or have no `main exit` can not be compared
+/// and return `None`, like programs whose original run times out. An
+/// optimised run that times out when the original did not is a difference.
+#[must_use]
+pub fn compare(source: &str, level: OptLevel) -> Option<Difference> {
+    let root = parse(source);
+    let mut program = compile(source.to_string(), &root, Builtin::resolve).ok()?;
+    let main = program
+        .procedure_by_name("main")
+        .filter(|main| main.arguments.len() == 2)?
+        .id();
+    program.closure_analysis();
+    let mut optimised = program.clone();
+    let mut passes = PassManager::with_level(main, level).verify(true);
+    if let Err(error) = passes.run(&mut optimised) {
+        return Some(Difference::Pass(error));
+    }
+    let original = run(&program, main);
+    if original.outcome == Outcome::Timeout {
+        return None;
+    }
+    let optimised = run(&optimised, main);
+    (original != optimised).then_some(Difference::Runs {
+        original,
+        optimised,
+    })
+}
+
+/// Run `main exit` without capabilities. Calls of `print` are recorded but
+/// not printed.
+#[must_use]
+pub fn run(program: &Program<Builtin>, main: u32) -> Run {
+    let capabilities = Capabilities::default();
+    let mut effects = Vec::new();
+    let call = vec![Value::Closure(main, vec![]), Value::Builtin(Builtin::Exit)];
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        Scheduler::new(call).run_for(STEPS, |state| {
+            iterate(
+                program,
+                |program, call| {
+                    let Value::Builtin(builtin) = call[0] else {
+                        unreachable!()
+                    };
+                    if !builtin.is_pure() && builtin != Builtin::Exit {
+                        effects.push(effect(call));
+                    }
+                    if builtin == Builtin::Print {
+                        *call = vec![call[2].clone()];
+                        return None;
+                    }
+                    capabilities.evaluate(program, call)
+                },
+                state,
+            )
+        })
+    }));
+    let outcome = match result {
+        Ok(Some(status)) => Outcome::Exit(status),
+        Ok(None) => Outcome::Timeout,
+        Err(payload) => Outcome::Error(message(payload.as_ref())),
+    };
+    Run { effects, outcome }
+}
+
+/// Shrink `source` to a program for which `fails` still holds, by removing
+/// lines and replacing expressions by `0` until no single change keeps it
+/// failing.
+pub fn reduce(source: &str, mut fails: impl FnMut(&str) -> bool) -> String {
+    let mut source = source.to_string();
+    loop {
+        let mut changed = false;
+
+        // Remove lines.
+        let mut line = 0;
+        loop {
+            let lines = source.lines().collect::<Vec<_>>();
+            if line >= lines.len() {
+                break;
+            }
+            let mut candidate = String::new();
+            for (i, text) in lines.iter().enumerate() {
+                if i != line {
+                    candidate.push_str(text);
+                    candidate.push('\n');
+                }
+            }
+            if fails(&candidate) {
+                source = candidate;
+                changed = true;
+            } else {
+                line += 1;
+            }
+        }
+
+        // Replace expressions, outermost first.
+        let mut expression = 0;
+        loop {
+            let expressions = expressions(&parse(&source));
+            let Some(span) = expressions.get(expression) else {
+                break;
+            };
+            let candidate = format!("{}0{}", &source[..span.start], &source[span.end..]);
+            if fails(&candidate) {
+                source = candidate;
+                changed = true;
+            } else {
+                expression += 1;
+            }
+        }
+
+        if !changed {
+            return source;
+        }
+    }
+}
+
+/// Parenthesised expressions, numbers other than `0` and strings, in source
+/// order.
+fn expressions(node: &Node) -> Vec<Span> {
+    let mut spans = Vec::new();
+    for child in node.children_with_tokens() {
+        match child {
+            ElementRef::Node(node) => {
+                let parenthesised = node
+                    .first_child_or_token()
+                    .is_some_and(|first| first.kind() == Kind::ParenOpen);
+                if parenthesised {
+                    spans.push(node.span());
+                }
+                spans.extend(expressions(node));
+            }
+            ElementRef::Token(token) => {
+                if token.kind() == Kind::Number && token.text() != "0"
+                    || token.kind() == Kind::String
+                {
+                    spans.push(token.span());
+                }
+            }
+        }
+    }
+    spans
+}
+
+/// A builtin call, with closures shown as `<closure>`.
+fn effect(call: &[Value<Builtin>]) -> String {
+    call.iter().map(show).collect::<Vec<_>>().join(" ")
+}
+
+fn show(value: &Value<Builtin>) -> String {
+    match value {
+        Value::Closure(..) => "<closure>".to_string(),
+        Value::Array(values) => {
+            let values = values.iter().map(show).collect::<Vec<_>>();
+            format!("[{}]", values.join(" "))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// The message of a panic.
+fn message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+impl Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for effect in &self.effects {
+            writeln!(f, "  {effect}")?;
+        }
+        match &self.outcome {
+            Outcome::Exit(status) => write!(f, "  exit status {status}"),
+            Outcome::Error(message) => write!(f, "  runtime error: {message}"),
+            Outcome::Timeout => write!(f, "  timeout after {STEPS} steps"),
+        }
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pass(error) => write!(f, "{error}"),
+            Self::Runs {
+                original,
+                optimised,
+            } => write!(f, "original:\n{original}\noptimised:\n{optimised}"),
+        }
+    }
+}
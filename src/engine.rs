@@ -0,0 +1,1003 @@
+//! Embedding Oluś in Rust programs.
+//!
+//! An [`Engine`] compiles source code to a [`Module`], whose procedures can be
+//! called with Rust values. The last parameter of the procedure is passed a
+//! host continuation, [`Value::Halt`], and the values it is called with are
+//! returned to the caller:
+//!
+//! ```
+//! # use olus::engine::{Engine, EngineError};
+//! # fn main() -> Result<(), EngineError> {
+//! let mut engine = Engine::new();
+//! let module = engine.compile("double.olus", "double x ret: ret (mul x 2)\n")?;
+//! let result: i64 = module.call("double", 21_i64)?;
+//! assert_eq!(result, 42);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Values are converted with [`IntoValue`] and [`FromValue`], and argument
+//! lists with [`IntoValues`] and [`FromValues`], which are implemented for
+//! single values and tuples.
+//!
+//! An evaluation can also be run for a number of steps at a time with
+//! [`Module::start`] and [`Module::run_for`], and a paused one saved with
+//! [`Module::snapshot`] and continued later with [`Module::resume`]:
+//!
+//! ```
+//! # use olus::engine::{Engine, EngineError, Module};
+//! fn run(module: &Module) -> Result<i64, EngineError> {
+//!     let mut paused = module.start("main", ())?;
+//!     if let Some(values) = module.run_for(&mut paused, 10)? {
+//!         return Ok(values.len() as i64);
+//!     }
+//!     let bytes = module.snapshot(&paused);
+//!     let mut paused = module.resume(&bytes)?;
+//!     module.finish(&mut paused)
+//! }
+//! # let source = "main ret: count 100 ret\ncount n ret: is_zero n (z:)\n    \
+//! #     if z (: ret 42) (: sub n 1 (m: count m ret))\n";
+//! # let module = Engine::new().compile("count.olus", source)?;
+//! # assert_eq!(run(&module)?, 42);
+//! # Ok::<(), EngineError>(())
+//! ```
+//!
+//! Rust functions registered with [`Engine::function`] are called by name like
+//! builtins. They can call back into the program with [`Module::apply`]:
+//!
+//! ```
+//! # use olus::{builtins::Builtin, engine::{Engine, EngineError}, interpreter::Value};
+//! # fn main() -> Result<(), EngineError> {
+//! let mut engine = Engine::new().function("twice", 3, |module, arguments| {
+//!     let [f, x, ret] = arguments else {
+//!         unreachable!()
+//!     };
+//!     let y: Value<Builtin> = module.apply(f.clone(), x.clone())?;
+//!     let z: Value<Builtin> = module.apply(f.clone(), y)?;
+//!     Ok(vec![ret.clone(), z])
+//! });
+//! let module = engine.compile("twice.olus", "main ret: twice (x k: mul x 3 k) 2 ret\n")?;
+//! assert_eq!(module.call::<i64>("main", ())?, 18);
+//! # Ok(())
+//! # }
+//! ```
+
+use {
+    crate::{
+        Diagnostic, FileId, Files, Span,
+        builtins::{Builtin, Capabilities, assertion_message},
+        front::{compile, parse},
+        interpreter::{Key, Scheduler, SnapshotError, Value, iterate},
+        ir::{Flow, Number, Program, Test},
+    },
+    core::fmt::{self, Debug, Display},
+    num_traits::ToPrimitive,
+    std::{collections::HashMap, error::Error, hash::BuildHasher, io, path::PathBuf, rc::Rc},
+};
+
+/// Compiles modules and keeps their sources for diagnostics.
+#[derive(Default)]
+pub struct Engine {
+    files:        Files,
+    capabilities: Capabilities,
+    functions:    Vec<HostFunction>,
+}
+
+/// A compiled program whose procedures can be called.
+#[derive(Clone, Debug)]
+pub struct Module {
+    file:         FileId,
+    program:      Program<Builtin>,
+    capabilities: Capabilities,
+    functions:    Rc<[HostFunction]>,
+}
+
+/// An evaluation of a module that has not finished yet, see
+/// [`Module::start`].
+#[derive(Clone, Debug)]
+pub struct Paused {
+    scheduler: Scheduler<Builtin>,
+    /// The procedure entered last, whose body makes the builtin calls.
+    caller:    Option<u32>,
+}
+
+/// A Rust function called from Oluś. It receives the module and all
+/// arguments, including continuations, and returns the next call.
+pub type Function = dyn Fn(&Module, &[Value<Builtin>]) -> Result<Vec<Value<Builtin>>, EngineError>;
+
+#[derive(Clone)]
+struct HostFunction {
+    name:     String,
+    arity:    usize,
+    function: Rc<Function>,
+}
+
+/// Reasons loading or calling a module can fail.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The source file could not be read.
+    Io(io::Error),
+    /// The source did not compile.
+    Compile(Vec<Diagnostic>),
+    /// There is no procedure with this name that can be called from the
+    /// host, i.e. one without captured variables.
+    UnknownProcedure { name: String },
+    /// The procedure takes a different number of arguments, not counting the
+    /// continuation.
+    Arguments {
+        name:     String,
+        expected: usize,
+        found:    usize,
+    },
+    /// A host function was called with a different number of arguments than
+    /// its arity, including continuations.
+    HostArguments {
+        name:     String,
+        expected: usize,
+        found:    usize,
+    },
+    /// A builtin was called with a different number of arguments than its
+    /// arity, including continuations.
+    BuiltinArguments {
+        builtin:  Builtin,
+        expected: usize,
+        found:    usize,
+    },
+    /// The continuation was called with a different number of values.
+    Results { expected: usize, found: usize },
+    /// A value does not convert to the requested Rust type.
+    Conversion {
+        expected: &'static str,
+        found:    String,
+    },
+    /// The program called `exit` with this status.
+    Exit(i32),
+    /// An `assert_eq` or `assert_ne` failed.
+    Assertion(Box<Assertion>),
+    /// A snapshot could not be resumed.
+    Snapshot(SnapshotError),
+    /// A builtin was called without the capability it needs, see
+    /// [`Capabilities::missing`].
+    Capability {
+        builtin:    Builtin,
+        capability: &'static str,
+    },
+}
+
+/// A failed `assert_eq` or `assert_ne`.
+#[derive(Clone, Debug)]
+pub struct Assertion {
+    pub builtin:  Builtin,
+    pub expected: Value<Builtin>,
+    pub actual:   Value<Builtin>,
+    /// The call of the assertion.
+    pub span:     Span,
+}
+
+impl Engine {
+    /// An engine whose modules have no capabilities.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capabilities granted to modules compiled from now on.
+    #[must_use]
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Make `function` available to modules compiled from now on as a builtin
+    /// called `name` taking `arity` arguments. Builtins of the same name take
+    /// precedence.
+    #[must_use]
+    pub fn function(
+        mut self,
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&Module, &[Value<Builtin>]) -> Result<Vec<Value<Builtin>>, EngineError>
+        + 'static,
+    ) -> Self {
+        self.functions.push(HostFunction {
+            name: name.into(),
+            arity,
+            function: Rc::new(function),
+        });
+        self
+    }
+
+    /// Sources of all modules, for reporting diagnostics.
+    #[must_use]
+    pub const fn files(&self) -> &Files {
+        &self.files
+    }
+
+    /// Compile the source file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or does not compile.
+    pub fn load(&mut self, path: PathBuf) -> Result<Module, EngineError> {
+        let file = self.files.insert(path).map_err(EngineError::Io)?;
+        self.build(file)
+    }
+
+    /// Compile `source`, using `path` only in diagnostics.
+    ///
+    /// # Errors
+    ///
+    /// Returns the diagnostics if the source does not compile.
+    pub fn compile(
+        &mut self,
+        path: impl Into<PathBuf>,
+        source: impl Into<String>,
+    ) -> Result<Module, EngineError> {
+        let file = self.files.insert_source(path.into(), source.into());
+        self.build(file)
+    }
+
+    /// Compile `source` in place of the contents of `file`, which was added by
+    /// [`Engine::compile`]. Diagnostics of earlier modules of the file refer
+    /// to the new contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns the diagnostics if the source does not compile.
+    pub fn recompile(
+        &mut self,
+        file: FileId,
+        source: impl Into<String>,
+    ) -> Result<Module, EngineError> {
+        self.files.replace_source(file, source.into());
+        self.build(file)
+    }
+
+    fn build(&self, file: FileId) -> Result<Module, EngineError> {
+        let source = self.files[file].contents();
+        let root = parse(source);
+        let resolve = |name: &str| {
+            Builtin::resolve(name).or_else(|| {
+                let index = self.functions.iter().position(|f| f.name == name)?;
+                let arity = self.functions[index].arity;
+                Some(Builtin::Host { index, arity })
+            })
+        };
+        let mut program = compile(source.to_string(), &root, resolve).map_err(|errors| {
+            EngineError::Compile(errors.iter().map(|error| error.diagnostic(file)).collect())
+        })?;
+        program.closure_analysis();
+        Ok(Module {
+            file,
+            program,
+            capabilities: self.capabilities.clone(),
+            functions: self.functions.clone().into(),
+        })
+    }
+}
+
+impl Module {
+    /// The file the module was compiled from.
+    #[must_use]
+    pub const fn file(&self) -> FileId {
+        self.file
+    }
+
+    #[must_use]
+    pub const fn program(&self) -> &Program<Builtin> {
+        &self.program
+    }
+
+    /// The tests declared in the module, see [`Module::test`].
+    #[must_use]
+    pub fn tests(&self) -> &[Test] {
+        &self.program.tests
+    }
+
+    /// Run a test of this module. It passes if it calls its continuation.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EngineError::Assertion`] if an assertion fails, or an error
+    /// if the test exits, lacks a capability or a host function fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a runtime error in the test, see [`Capabilities::evaluate`].
+    pub fn test(&self, test: &Test) -> Result<(), EngineError> {
+        self.evaluate(vec![Value::Closure(test.id, vec![]), Value::Halt])
+            .map(drop)
+    }
+
+    /// Call procedure `name` with `arguments` followed by a continuation and
+    /// return the values the continuation is called with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such procedure, the number or types of
+    /// the arguments or results do not match, or the program exits or lacks a
+    /// capability.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a runtime error in the program, see
+    /// [`Capabilities::evaluate`].
+    pub fn call<R: FromValues>(
+        &self,
+        name: &str,
+        arguments: impl IntoValues,
+    ) -> Result<R, EngineError> {
+        let call = self.call_of(name, arguments)?;
+        R::from_values(self.evaluate(call)?)
+    }
+
+    /// Start a call like [`Module::call`] without running it yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such procedure or the number of
+    /// arguments does not match.
+    pub fn start(&self, name: &str, arguments: impl IntoValues) -> Result<Paused, EngineError> {
+        let call = self.call_of(name, arguments)?;
+        Ok(Paused {
+            scheduler: Scheduler::new(call),
+            caller:    None,
+        })
+    }
+
+    /// Run a paused evaluation for at most `steps` steps. Returns the values
+    /// [`Value::Halt`] is called with once it finishes, or `None` if it is
+    /// paused again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program exits, lacks a capability or a host
+    /// function fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a runtime error in the program, see
+    /// [`Capabilities::evaluate`].
+    pub fn run_for(
+        &self,
+        paused: &mut Paused,
+        steps: usize,
+    ) -> Result<Option<Vec<Value<Builtin>>>, EngineError> {
+        let Paused { scheduler, caller } = paused;
+        scheduler
+            .run_for(steps, |state| {
+                match state.call.as_slice() {
+                    [Value::Halt, values @ ..] => return Some(Ok(values.to_vec())),
+                    [Value::Closure(id, _), ..] => *caller = Some(*id),
+                    _ => {}
+                }
+                iterate(
+                    &self.program,
+                    |program, call| self.builtin(program, call, *caller),
+                    state,
+                )
+            })
+            .transpose()
+    }
+
+    /// Run a paused evaluation until it finishes and convert the values
+    /// [`Value::Halt`] is called with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error like [`Module::run_for`] or if the results do not
+    /// convert.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a runtime error in the program, see
+    /// [`Capabilities::evaluate`].
+    pub fn finish<R: FromValues>(&self, paused: &mut Paused) -> Result<R, EngineError> {
+        loop {
+            if let Some(values) = self.run_for(paused, usize::MAX)? {
+                return R::from_values(values);
+            }
+        }
+    }
+
+    /// Save a paused evaluation to bytes, see [`crate::interpreter`].
+    #[must_use]
+    pub fn snapshot(&self, paused: &Paused) -> Vec<u8> {
+        paused.scheduler.snapshot(&self.program)
+    }
+
+    /// Restore a paused evaluation saved by [`Module::snapshot`] of this
+    /// module, or of one compiled from the same source with the same host
+    /// functions.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EngineError::Snapshot`] if the bytes are not a snapshot of
+    /// this module.
+    pub fn resume(&self, bytes: &[u8]) -> Result<Paused, EngineError> {
+        let builtins = |text: &str| {
+            Builtin::decode(text).filter(|builtin| match *builtin {
+                Builtin::Host { index, arity } => self
+                    .functions
+                    .get(index)
+                    .is_some_and(|function| function.arity == arity),
+                _ => true,
+            })
+        };
+        let scheduler =
+            Scheduler::resume(&self.program, builtins, bytes).map_err(EngineError::Snapshot)?;
+        Ok(Paused {
+            scheduler,
+            caller: None,
+        })
+    }
+
+    /// The initial call of procedure `name` with `arguments` followed by a
+    /// continuation.
+    fn call_of(
+        &self,
+        name: &str,
+        arguments: impl IntoValues,
+    ) -> Result<Vec<Value<Builtin>>, EngineError> {
+        let procedure = self
+            .program
+            .procedure_by_name(name)
+            .filter(|procedure| procedure.closure.is_empty())
+            .ok_or_else(|| EngineError::UnknownProcedure {
+                name: name.to_string(),
+            })?;
+        let mut call = vec![Value::Closure(procedure.id(), vec![])];
+        call.extend(arguments.into_values());
+        call.push(Value::Halt);
+        if call.len() != procedure.arguments.len() {
+            return Err(EngineError::Arguments {
+                name:     name.to_string(),
+                expected: procedure.arguments.len().saturating_sub(2),
+                found:    call.len() - 2,
+            });
+        }
+        Ok(call)
+    }
+
+    /// Call `function`, usually a closure, with `arguments` followed by a
+    /// continuation and return the values the continuation is called with.
+    /// This can be used from a host function while the module is running.
+    ///
+    /// The call is evaluated on its own, so it can not perform effects that
+    /// are handled by its caller or use the fibers and channels of its
+    /// caller.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the types or number of the results do not match or
+    /// the program exits or lacks a capability.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a runtime error in the program, see
+    /// [`Capabilities::evaluate`].
+    pub fn apply<R: FromValues>(
+        &self,
+        function: Value<Builtin>,
+        arguments: impl IntoValues,
+    ) -> Result<R, EngineError> {
+        let mut call = vec![function];
+        call.extend(arguments.into_values());
+        call.push(Value::Halt);
+        R::from_values(self.evaluate(call)?)
+    }
+
+    /// Evaluate `call` until [`Value::Halt`] is called and return the values
+    /// it is called with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program exits, lacks a capability or a host
+    /// function fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a runtime error in the program, see
+    /// [`Capabilities::evaluate`].
+    pub fn evaluate(&self, call: Vec<Value<Builtin>>) -> Result<Vec<Value<Builtin>>, EngineError> {
+        let mut paused = Paused {
+            scheduler: Scheduler::new(call),
+            caller:    None,
+        };
+        loop {
+            if let Some(values) = self.run_for(&mut paused, usize::MAX)? {
+                return Ok(values);
+            }
+        }
+    }
+
+    /// Evaluate a call to a builtin or host function.
+    fn builtin(
+        &self,
+        program: &Program<Builtin>,
+        call: &mut Vec<Value<Builtin>>,
+        caller: Option<u32>,
+    ) -> Option<Result<Vec<Value<Builtin>>, EngineError>> {
+        if let Value::Builtin(builtin) = call[0]
+            && let Some(capability) = self.capabilities.missing(builtin)
+        {
+            return Some(Err(EngineError::Capability {
+                builtin,
+                capability,
+            }));
+        }
+        let (index, arity) = match call[0] {
+            Value::Builtin(Builtin::Host { index, arity }) => (index, arity),
+            Value::Builtin(builtin @ (Builtin::AssertEq | Builtin::AssertNe))
+                if call.len() != builtin.arity() + 1 =>
+            {
+                return Some(Err(EngineError::BuiltinArguments {
+                    builtin,
+                    expected: builtin.arity(),
+                    found: call.len() - 1,
+                }));
+            }
+            Value::Builtin(builtin @ (Builtin::AssertEq | Builtin::AssertNe))
+                if !builtin.assertion(&call[1], &call[2]) =>
+            {
+                return Some(Err(EngineError::Assertion(Box::new(Assertion {
+                    builtin,
+                    expected: call[2].clone(),
+                    actual: call[1].clone(),
+                    span: self.call_span(caller),
+                }))));
+            }
+            _ => {
+                return self
+                    .capabilities
+                    .evaluate(program, call)
+                    .map(|status| Err(EngineError::Exit(status)));
+            }
+        };
+        let HostFunction { name, function, .. } = &self.functions[index];
+        if call.len() != arity + 1 {
+            return Some(Err(EngineError::HostArguments {
+                name:     name.clone(),
+                expected: arity,
+                found:    call.len() - 1,
+            }));
+        }
+        match function(self, &call[1..]) {
+            Ok(next) => {
+                *call = next;
+                None
+            }
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// Span of the call in the body of procedure `id`.
+    fn call_span(&self, id: Option<u32>) -> Span {
+        let body = id
+            .and_then(|id| self.program.procedure_by_id(id))
+            .map_or(&[][..], |procedure| procedure.body.as_slice());
+        let range = match (body.first(), body.last()) {
+            (Some(first), Some(last)) => first.source().start..last.source().end,
+            _ => 0..0,
+        };
+        self.file.span(range)
+    }
+}
+
+/// Conversion of a Rust value to an Oluś value.
+pub trait IntoValue {
+    fn into_value(self) -> Value<Builtin>;
+}
+
+/// Conversion of an Oluś value to a Rust value.
+pub trait FromValue: Sized {
+    /// # Errors
+    ///
+    /// Returns [`EngineError::Conversion`] if the value has a different type.
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError>;
+}
+
+/// Conversion of Rust values to the arguments of a call.
+pub trait IntoValues {
+    fn into_values(self) -> Vec<Value<Builtin>>;
+}
+
+/// Conversion of the arguments of a call to Rust values.
+pub trait FromValues: Sized {
+    /// # Errors
+    ///
+    /// Returns an error if the number or types of the values do not match.
+    fn from_values(values: Vec<Value<Builtin>>) -> Result<Self, EngineError>;
+}
+
+impl<T: IntoValue> IntoValues for T {
+    fn into_values(self) -> Vec<Value<Builtin>> {
+        vec![self.into_value()]
+    }
+}
+
+impl<T: FromValue> FromValues for T {
+    fn from_values(values: Vec<Value<Builtin>>) -> Result<Self, EngineError> {
+        let [value] = <[_; 1]>::try_from(values).map_err(|values| EngineError::Results {
+            expected: 1,
+            found:    values.len(),
+        })?;
+        T::from_value(value)
+    }
+}
+
+impl IntoValues for () {
+    fn into_values(self) -> Vec<Value<Builtin>> {
+        Vec::new()
+    }
+}
+
+impl FromValues for () {
+    fn from_values(values: Vec<Value<Builtin>>) -> Result<Self, EngineError> {
+        if values.is_empty() {
+            Ok(())
+        } else {
+            Err(EngineError::Results {
+                expected: 0,
+                found:    values.len(),
+            })
+        }
+    }
+}
+
+/// Implement [`IntoValues`] and [`FromValues`] for a tuple.
+macro_rules! tuple {
+    ($count:literal, $($type:ident $value:ident),*) => {
+        impl<$($type: IntoValue),*> IntoValues for ($($type,)*) {
+            fn into_values(self) -> Vec<Value<Builtin>> {
+                let ($($value,)*) = self;
+                vec![$($value.into_value()),*]
+            }
+        }
+
+        impl<$($type: FromValue),*> FromValues for ($($type,)*) {
+            fn from_values(values: Vec<Value<Builtin>>) -> Result<Self, EngineError> {
+                let [$($value),*] =
+                    <[_; $count]>::try_from(values).map_err(|values| EngineError::Results {
+                        expected: $count,
+                        found:    values.len(),
+                    })?;
+                Ok(($($type::from_value($value)?,)*))
+            }
+        }
+    };
+}
+
+tuple!(2, A a, B b);
+tuple!(3, A a, B b, C c);
+tuple!(4, A a, B b, C c, D d);
+
+impl IntoValue for Value<Builtin> {
+    fn into_value(self) -> Value<Builtin> {
+        self
+    }
+}
+
+impl FromValue for Value<Builtin> {
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for Number {
+    fn into_value(self) -> Value<Builtin> {
+        Value::Number(self)
+    }
+}
+
+impl FromValue for Number {
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError> {
+        match value {
+            Value::Number(n) => Ok(n),
+            value => Err(EngineError::conversion("a number", &value)),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value<Builtin> {
+        Value::Number(self.into())
+    }
+}
+
+/// Only integers that fit convert to `i64`.
+impl FromValue for i64 {
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError> {
+        match &value {
+            Value::Number(Number::Integer(n)) => n.to_i64(),
+            _ => None,
+        }
+        .ok_or_else(|| EngineError::conversion("an `i64` integer", &value))
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value<Builtin> {
+        Value::Number(self.into())
+    }
+}
+
+/// Integers convert to the nearest float.
+impl FromValue for f64 {
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError> {
+        Number::from_value(value).map(|n| n.to_f64())
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value<Builtin> {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value<Builtin> {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError> {
+        match value {
+            Value::String(s) => Ok(s),
+            value => Err(EngineError::conversion("a string", &value)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value<Builtin> {
+        Value::Array(Rc::new(
+            self.into_iter().map(IntoValue::into_value).collect(),
+        ))
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError> {
+        match value {
+            Value::Array(values) => Rc::unwrap_or_clone(values)
+                .into_iter()
+                .map(T::from_value)
+                .collect(),
+            value => Err(EngineError::conversion("an array", &value)),
+        }
+    }
+}
+
+impl<T: IntoValue, S> IntoValue for HashMap<String, T, S> {
+    fn into_value(self) -> Value<Builtin> {
+        let entries = self
+            .into_iter()
+            .map(|(key, value)| (Key::String(key), value.into_value()));
+        Value::Map(Rc::new(entries.collect()))
+    }
+}
+
+/// Only maps with string keys convert.
+impl<T: FromValue, S: BuildHasher + Default> FromValue for HashMap<String, T, S> {
+    fn from_value(value: Value<Builtin>) -> Result<Self, EngineError> {
+        let Value::Map(entries) = &value else {
+            return Err(EngineError::conversion("a map", &value));
+        };
+        entries
+            .iter()
+            .map(|(key, entry)| match key {
+                Key::String(key) => Ok((key.clone(), T::from_value(entry.clone())?)),
+                Key::Number(_) => Err(EngineError::conversion("a map with string keys", &value)),
+            })
+            .collect()
+    }
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostFunction({}/{})", self.name, self.arity)
+    }
+}
+
+impl EngineError {
+    fn conversion(expected: &'static str, found: &Value<Builtin>) -> Self {
+        Self::Conversion {
+            expected,
+            found: found.to_string(),
+        }
+    }
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Compile(diagnostics) => {
+                write!(f, "compilation failed")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n{}", diagnostic.message)?;
+                }
+                Ok(())
+            }
+            Self::UnknownProcedure { name } => write!(f, "no procedure `{name}` to call"),
+            Self::Arguments {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "procedure `{name}` takes {expected} arguments and a continuation, got {found} \
+                 arguments"
+            ),
+            Self::HostArguments {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "host function `{name}` takes {expected} arguments, got {found}"
+            ),
+            Self::BuiltinArguments {
+                builtin,
+                expected,
+                found,
+            } => write!(
+                f,
+                "builtin `{builtin}` takes {expected} arguments, got {found}"
+            ),
+            Self::Results { expected, found } => {
+                write!(f, "expected {expected} results, got {found}")
+            }
+            Self::Conversion { expected, found } => write!(f, "expected {expected}, got {found}"),
+            Self::Exit(status) => write!(f, "program exited with status {status}"),
+            Self::Assertion(assertion) => write!(f, "{assertion}"),
+            Self::Snapshot(error) => write!(f, "{error}"),
+            Self::Capability {
+                builtin,
+                capability,
+            } => write!(
+                f,
+                "the `{builtin}` builtin requires the `{capability}` capability"
+            ),
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = assertion_message(self.builtin, &self.expected, &self.actual);
+        f.write_str(&message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities() {
+        let source = "now ret: clock ret\n";
+        let module = Engine::new().compile("clock.olus", source).unwrap();
+        let result = module.call::<f64>("now", ());
+        assert!(matches!(
+            result,
+            Err(EngineError::Capability {
+                builtin:    Builtin::Clock,
+                capability: "clock",
+            })
+        ));
+
+        let capabilities = Capabilities {
+            clock: true,
+            ..Capabilities::default()
+        };
+        let mut engine = Engine::new().capabilities(capabilities);
+        let module = engine.compile("clock.olus", source).unwrap();
+        assert!(module.call::<f64>("now", ()).unwrap() > 0.0);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let source = "sum n ret: if (is_zero n) (: ret 0) (: sum (sub n 1) (s: ret (add n s)))\n";
+        let mut engine = Engine::new();
+        let module = engine.compile("sum.olus", source).unwrap();
+        let mut paused = module.start("sum", 100_i64).unwrap();
+        assert!(module.run_for(&mut paused, 10).unwrap().is_none());
+        let bytes = module.snapshot(&paused);
+
+        let other = engine.compile("sum.olus", source).unwrap();
+        let mut resumed = other.resume(&bytes).unwrap();
+        assert_eq!(other.finish::<i64>(&mut resumed).unwrap(), 5050);
+        assert_eq!(module.finish::<i64>(&mut paused).unwrap(), 5050);
+    }
+
+    #[test]
+    fn resume_host_functions() {
+        let source = "apply f x ret: f x ret\n";
+        let increment = |_: &Module, arguments: &[Value<Builtin>]| {
+            let x = i64::from_value(arguments[0].clone())?;
+            Ok(vec![arguments[1].clone(), (x + 1).into_value()])
+        };
+        let mut engine = Engine::new().function("increment", 2, increment);
+        let module = engine.compile("apply.olus", source).unwrap();
+        let host = Value::Builtin(Builtin::Host { index: 0, arity: 2 });
+        let bytes = module.snapshot(&module.start("apply", (host, 1_i64)).unwrap());
+
+        let mut paused = module.resume(&bytes).unwrap();
+        assert_eq!(module.finish::<i64>(&mut paused).unwrap(), 2);
+        let other = Engine::new().compile("apply.olus", source).unwrap();
+        assert!(matches!(
+            other.resume(&bytes),
+            Err(EngineError::Snapshot(SnapshotError::UnknownBuiltin { .. }))
+        ));
+    }
+
+    #[test]
+    fn host_arguments() {
+        let mut engine = Engine::new().function("pair", 3, |_, arguments| {
+            Ok(vec![arguments[2].clone(), arguments[0].clone()])
+        });
+        let module = engine
+            .compile("pair.olus", "good ret: pair 1 2 ret\nbad ret: pair 1 ret\n")
+            .unwrap();
+        assert_eq!(module.call::<i64>("good", ()).unwrap(), 1);
+        assert!(matches!(
+            module.call::<i64>("bad", ()),
+            Err(EngineError::HostArguments {
+                expected: 3,
+                found: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn assertion_arguments() {
+        let source = "good ret: assert_eq 1 1 (: ret 1)\nbad ret: assert_eq 1 (: ret 1)\n";
+        let module = Engine::new().compile("assert.olus", source).unwrap();
+        assert_eq!(module.call::<i64>("good", ()).unwrap(), 1);
+        assert!(matches!(
+            module.call::<i64>("bad", ()),
+            Err(EngineError::BuiltinArguments {
+                builtin:  Builtin::AssertEq,
+                expected: 3,
+                found:    2,
+            })
+        ));
+    }
+
+    #[test]
+    fn call_errors() {
+        let source = "split x ret: ret x (concat x “!”)\n";
+        let module = Engine::new().compile("split.olus", source).unwrap();
+        let (a, b): (String, String) = module.call("split", "hi").unwrap();
+        assert_eq!((a.as_str(), b.as_str()), ("hi", "hi!"));
+        assert!(matches!(
+            module.call::<String>("split", "hi"),
+            Err(EngineError::Results {
+                expected: 1,
+                found:    2,
+            })
+        ));
+        assert!(matches!(
+            module.call::<(i64, i64)>("split", "hi"),
+            Err(EngineError::Conversion { .. })
+        ));
+        assert!(matches!(
+            module.call::<()>("split", ()),
+            Err(EngineError::Arguments {
+                expected: 1,
+                found: 0,
+                ..
+            })
+        ));
+        assert!(matches!(
+            module.call::<()>("missing", ()),
+            Err(EngineError::UnknownProcedure { .. })
+        ));
+    }
+}
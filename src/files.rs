@@ -3,6 +3,7 @@ use {
     std::{
         fs::read_to_string,
         io,
+        mem::take,
         ops::{Index, Range},
         path::{Path, PathBuf},
     },
@@ -41,6 +42,21 @@ impl Files {
         self.files.push(File::new(path)?);
         Ok(FileId(id))
     }
+
+    /// Add source code that is not read from disk, `path` is only used in
+    /// diagnostics.
+    pub fn insert_source(&mut self, path: PathBuf, contents: String) -> FileId {
+        let id = self.files.len();
+        self.files.push(File::from_source(path, contents));
+        FileId(id)
+    }
+
+    /// Replace the contents of a file added with [`Files::insert_source`], for
+    /// sources that change like an interactive session.
+    pub fn replace_source(&mut self, id: FileId, contents: String) {
+        let file = &mut self.files[id.0];
+        *file = File::from_source(take(&mut file.path), contents);
+    }
 }
 
 impl Default for Files {
@@ -84,12 +100,16 @@ impl FileId {
 impl File {
     fn new(path: PathBuf) -> io::Result<Self> {
         let contents = read_to_string(&path)?;
+        Ok(Self::from_source(path, contents))
+    }
+
+    fn from_source(path: PathBuf, contents: String) -> Self {
         let source = Source::from(contents.clone());
-        Ok(Self {
+        Self {
             path,
             contents,
             source,
-        })
+        }
     }
 
     pub fn name(&self) -> &Path {
@@ -1,6 +1,9 @@
 use {
-    super::{ElementRef, Kind, Node, NodeExt, Span, Token, TokenExt},
-    crate::ir::{Atom, Identifier, Procedure, Program},
+    super::{ElementRef, Kind, Node, NodeExt, Span, Token, TokenExt, string::unquote},
+    crate::{
+        Diagnostic, FileId,
+        ir::{Atom, Constructor, Data, Identifier, Procedure, Program, Test},
+    },
     core::mem::{replace, swap},
 };
 
@@ -17,28 +20,56 @@ enum Expression<B> {
     },
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CompileError {
+    pub source:  Span,
+    pub message: String,
+}
+
 struct Compiler<B, F> {
     identifiers: Vec<Identifier>,
     program:     Program<B>,
     builtins:    F,
+    errors:      Vec<CompileError>,
 }
 
-#[must_use]
+/// Compile a syntax tree to a program.
+///
+/// # Errors
+///
+/// Returns all errors found, for example matches with missing cases.
 pub fn compile<B, F: FnMut(&str) -> Option<B>>(
     source: String,
     root: &Node,
     builtins: F,
-) -> Program<B> {
+) -> Result<Program<B>, Vec<CompileError>> {
     let mut compiler = Compiler {
         identifiers: Vec::new(),
         program: Program {
             source,
             procedures: Vec::new(),
+            data: Vec::new(),
+            tests: Vec::new(),
         },
         builtins,
+        errors: Vec::new(),
     };
     compiler.compile_node(root);
-    compiler.program
+    if compiler.errors.is_empty() {
+        Ok(compiler.program)
+    } else {
+        Err(compiler.errors)
+    }
+}
+
+impl CompileError {
+    #[must_use]
+    pub fn diagnostic(&self, file: FileId) -> Diagnostic {
+        Diagnostic {
+            message: self.message.clone(),
+            span:    file.span(self.source.start..self.source.end),
+        }
+    }
 }
 
 impl<B> Expression<B> {
@@ -69,12 +100,7 @@ impl<B, F: FnMut(&str) -> Option<B>> Compiler<B, F> {
                             .map(|t| self.parse_binder(t))
                     })
                     .collect();
-                let body = node
-                    .call()
-                    .expect("Could not resolve procedure body.")
-                    .children_with_tokens()
-                    .filter_map(|e| self.parse_expression(e))
-                    .collect();
+                let body = self.parse_body(node);
                 let body = self.compile_call(body);
                 self.program.procedures.push(Procedure {
                     source,
@@ -83,13 +109,134 @@ impl<B, F: FnMut(&str) -> Option<B>> Compiler<B, F> {
                     closure: vec![],
                 });
             }
+            Kind::Call if is_test(node) => self.compile_test(node),
             Kind::Call => {
                 // TODO: Detect unbound calls.
             }
+            Kind::Data => self.compile_data(node),
             _ => unreachable!(),
         }
     }
 
+    /// Expand a data declaration into Scott encoded constructors.
+    fn compile_data(&mut self, node: &Node) {
+        let constructors = node
+            .children()
+            .filter(|n| n.kind() == Kind::Constructor)
+            .collect::<Vec<_>>();
+        let mut data = Data {
+            source:       node.span(),
+            name:         name(node).to_string(),
+            constructors: Vec::new(),
+        };
+        for (index, constructor) in constructors.iter().enumerate() {
+            let source = constructor.span();
+            let mut tokens = constructor
+                .children_with_tokens()
+                .filter_map(ElementRef::into_token)
+                .filter(|t| t.kind() == Kind::Identifier);
+            let token = tokens.next().expect("Constructor has a name.");
+            if self.is_constructor(token.text(), &data) {
+                let message = format!("Duplicate constructor `{}`.", token.text());
+                self.error(token.span(), message);
+            }
+            let binder = self.parse_binder(token);
+            let fields = tokens
+                .map(|t| self.fresh_variable(true, t.span()))
+                .collect::<Vec<_>>();
+            let (ret, ret_reference) = self.fresh_variable(false, source);
+            let (value, value_reference) = self.fresh_variable(false, source);
+            let cases = constructors
+                .iter()
+                .map(|_| self.fresh_variable(false, source))
+                .collect::<Vec<_>>();
+
+            // `name fields… ret: ret value`
+            let mut arguments = vec![binder];
+            arguments.extend(fields.iter().map(|f| f.0));
+            arguments.push(ret);
+            self.program.procedures.push(Procedure {
+                source,
+                arguments,
+                body: vec![ret_reference, value_reference],
+                closure: vec![],
+            });
+
+            // `value cases…: case fields…`
+            let mut arguments = vec![value];
+            arguments.extend(cases.iter().map(|c| c.0));
+            let mut body = vec![Atom::Reference {
+                source,
+                id: cases[index].0.id,
+            }];
+            body.extend(fields.into_iter().map(|f| f.1));
+            data.constructors.push(Constructor {
+                id:     binder.id,
+                value:  value.id,
+                fields: body.len() - 1,
+            });
+            self.program.procedures.push(Procedure {
+                source,
+                arguments,
+                body,
+                closure: vec![],
+            });
+        }
+        self.program.data.push(data);
+    }
+
+    /// Whether `name` is a constructor of `data` or of an earlier declaration.
+    fn is_constructor(&self, name: &str, data: &Data) -> bool {
+        self.program
+            .data
+            .iter()
+            .chain([data])
+            .flat_map(|data| &data.constructors)
+            .any(|constructor| {
+                let source = self.identifiers[constructor.id as usize].source;
+                self.program.source[source.start..source.end] == *name
+            })
+    }
+
+    /// Compile `test “name” call…` into a procedure `test done: call… done`.
+    fn compile_test(&mut self, node: &Node) {
+        let source = node.span();
+        let mut body = node
+            .children_with_tokens()
+            .skip_while(|e| e.kind() != Kind::Identifier)
+            .skip(1)
+            .filter_map(|e| self.parse_expression(e))
+            .collect::<Vec<_>>();
+        let Some(Expression::Atom(Atom::String { value: name, .. })) = body.first() else {
+            self.error(
+                source,
+                "A test needs a name: `test “name” call`.".to_string(),
+            );
+            return;
+        };
+        let name = name.clone();
+        body.remove(0);
+        if body.is_empty() {
+            self.error(source, format!("Test “{name}” has no body."));
+            return;
+        }
+        let (definition, _) = self.fresh_variable(false, source);
+        let (done, done_reference) = self.fresh_variable(false, source);
+        body.push(Expression::Atom(done_reference));
+        let body = self.compile_call(body);
+        self.program.procedures.push(Procedure {
+            source,
+            arguments: vec![definition, done],
+            body,
+            closure: vec![],
+        });
+        self.program.tests.push(Test {
+            source,
+            name,
+            id: definition.id,
+        });
+    }
+
     /// Compile a call of expressions into a call of atoms.
     fn compile_call(&mut self, mut expr: Vec<Expression<B>>) -> Vec<Atom<B>> {
         // First eliminate all call groups by converting them to procedure
@@ -144,6 +291,144 @@ impl<B, F: FnMut(&str) -> Option<B>> Compiler<B, F> {
             .collect()
     }
 
+    /// Parse the body of a procedure, see [`NodeExt::call`].
+    fn parse_body(&mut self, proc: &Node) -> Vec<Expression<B>> {
+        if let Some(call) = proc.call() {
+            self.parse_call(call)
+        } else {
+            self.error(proc.span(), "Procedure has no body.".to_string());
+            Vec::new()
+        }
+    }
+
+    /// Parse the expressions of a call, desugaring a match.
+    fn parse_call(&mut self, call: &Node) -> Vec<Expression<B>> {
+        if let Some(matching) = call.children().find(|n| n.kind() == Kind::Match) {
+            return self.parse_match(matching);
+        }
+        call.children_with_tokens()
+            .filter_map(|e| self.parse_expression(e))
+            .collect()
+    }
+
+    /// Desugar a match into a call of the value with one case per
+    /// constructor, in declaration order.
+    fn parse_match(&mut self, node: &Node) -> Vec<Expression<B>> {
+        let value = node
+            .children_with_tokens()
+            .find_map(|e| self.parse_expression(e))
+            .expect("Match has a value.");
+        let mut declaration = None;
+        let mut cases = Vec::new();
+        for case in node.children().filter(|n| n.kind() == Kind::Case) {
+            let constructor = case
+                .children_with_tokens()
+                .filter_map(ElementRef::into_token)
+                .find(|t| t.kind() == Kind::Identifier)
+                .expect("Case has a constructor.");
+            let proc = case
+                .children()
+                .find(|n| n.kind() == Kind::Proc)
+                .expect("Case has a procedure.");
+
+            // Find the constructor's declaration and position in it.
+            let Some(binder) = constructor
+                .resolve()
+                .filter(|b| b.parent().kind() == Kind::Constructor)
+            else {
+                self.error(
+                    constructor.span(),
+                    format!("`{}` is not a constructor.", constructor.text()),
+                );
+                continue;
+            };
+            let data = binder
+                .parent()
+                .parent()
+                .expect("Constructor is in a data node.");
+            let constructors = data
+                .children()
+                .filter(|n| n.kind() == Kind::Constructor)
+                .collect::<Vec<_>>();
+            let index = constructors
+                .iter()
+                .position(|c| *c == binder.parent())
+                .expect("Constructor is in its declaration.");
+            let fields = binder
+                .parent()
+                .children_with_tokens()
+                .filter(|e| e.kind() == Kind::Identifier)
+                .count()
+                - 1;
+            match declaration {
+                None => {
+                    declaration = Some(data);
+                    cases.resize_with(constructors.len(), || None);
+                }
+                Some(declaration) if declaration != data => {
+                    let message = format!(
+                        "`{}` is not a constructor of {}.",
+                        constructor.text(),
+                        name(declaration)
+                    );
+                    self.error(constructor.span(), message);
+                    continue;
+                }
+                Some(_) => {}
+            }
+
+            let arguments = proc
+                .children_with_tokens()
+                .filter_map(|n| {
+                    n.as_token()
+                        .filter(|t| t.is_binder())
+                        .map(|t| self.parse_binder(t))
+                })
+                .collect::<Vec<_>>();
+            if arguments.len() != fields {
+                let message = format!(
+                    "`{}` has {fields} fields but {} are bound.",
+                    constructor.text(),
+                    arguments.len()
+                );
+                self.error(case.span(), message);
+            }
+            if cases[index].is_some() {
+                let message = format!("Duplicate case `{}`.", constructor.text());
+                self.error(case.span(), message);
+                continue;
+            }
+            let body = self.parse_body(proc);
+            cases[index] = Some(Expression::Procedure {
+                source: case.span(),
+                arguments,
+                body,
+            });
+        }
+
+        if let Some(declaration) = declaration {
+            let missing = declaration
+                .children()
+                .filter(|n| n.kind() == Kind::Constructor)
+                .zip(&cases)
+                .filter(|(_, case)| case.is_none())
+                .map(|(constructor, _)| format!("`{}`", name(constructor)))
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                let message = format!(
+                    "Match on {} is missing {}.",
+                    name(declaration),
+                    missing.join(", ")
+                );
+                self.error(node.span(), message);
+            }
+        }
+
+        let mut call = vec![value];
+        call.extend(cases.into_iter().flatten());
+        call
+    }
+
     fn parse_expression(&mut self, expr: ElementRef) -> Option<Expression<B>> {
         match expr {
             ElementRef::Token(token) => self.parse_atom(token).map(Expression::Atom),
@@ -157,12 +442,7 @@ impl<B, F: FnMut(&str) -> Option<B>> Compiler<B, F> {
                             .map(|t| self.parse_binder(t))
                     })
                     .collect();
-                let body = node
-                    .call()
-                    .expect("Could not resolve procedure body.")
-                    .children_with_tokens()
-                    .filter_map(|e| self.parse_expression(e))
-                    .collect();
+                let body = self.parse_body(node);
                 Some(Expression::Procedure {
                     source,
                     arguments,
@@ -177,31 +457,129 @@ impl<B, F: FnMut(&str) -> Option<B>> Compiler<B, F> {
                     .collect();
                 Some(Expression::Call { source, body })
             }
+            ElementRef::Node(node) if node.kind() == Kind::Interpolation => {
+                Some(self.parse_interpolation(node))
+            }
             _ => None,
         }
     }
 
+    /// Desugar an interpolated string into `concat` calls on the text parts
+    /// and the interpolated values converted with `show`. An interpolation of
+    /// a single expression is its value, otherwise it is a call.
+    fn parse_interpolation(&mut self, node: &Node) -> Expression<B> {
+        let source = node.span();
+        let parts = node
+            .children_with_tokens()
+            .filter_map(ElementRef::into_token)
+            .filter(|t| {
+                matches!(
+                    t.kind(),
+                    Kind::StringStart | Kind::StringMiddle | Kind::StringEnd
+                )
+            })
+            .collect::<Vec<_>>();
+        let texts = parts.iter().map(|t| t.text()).collect::<Vec<_>>();
+        let texts = unquote(&self.program.source[source.start..source.end], &texts).unwrap_or_else(
+            |message| {
+                self.error(source, message);
+                vec![String::new(); parts.len()]
+            },
+        );
+        let values = node
+            .children()
+            .filter(|n| n.kind() == Kind::Call)
+            .map(|call| {
+                let mut body = call
+                    .children_with_tokens()
+                    .filter_map(|e| self.parse_expression(e))
+                    .collect::<Vec<_>>();
+                if body.len() == 1 {
+                    body.remove(0)
+                } else {
+                    Expression::Call {
+                        source: call.span(),
+                        body,
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut values = values.into_iter();
+        let mut pieces = Vec::new();
+        for (part, text) in parts.iter().zip(texts) {
+            if !text.is_empty() {
+                pieces.push(Expression::Atom(Atom::String {
+                    source: part.span(),
+                    value:  text,
+                }));
+            }
+            if let Some(value) = values.next() {
+                let source = value.source();
+                let show = self.interpolation_builtin(source, "show");
+                pieces.push(Expression::Call {
+                    source,
+                    body: vec![show, value],
+                });
+            }
+        }
+        pieces
+            .into_iter()
+            .reduce(|left, right| {
+                let concat = self.interpolation_builtin(source, "concat");
+                Expression::Call {
+                    source,
+                    body: vec![concat, left, right],
+                }
+            })
+            .expect("Interpolated string has a value.")
+    }
+
+    fn interpolation_builtin(&mut self, source: Span, name: &str) -> Expression<B> {
+        let Some(builtin) = (self.builtins)(name) else {
+            let message = format!("String interpolation requires the `{name}` builtin.");
+            self.error(source, message);
+            return Expression::Atom(Atom::String {
+                source,
+                value: String::new(),
+            });
+        };
+        Expression::Atom(Atom::Builtin { source, builtin })
+    }
+
     fn parse_binder(&mut self, identifier: &Token) -> Identifier {
         assert!(identifier.is_binder());
-        self.identifiers
+        if let Some(binder) = self
+            .identifiers
             .iter()
             .find(|i| i.source == identifier.span())
-            .copied()
-            .unwrap_or_else(|| self.fresh_variable(true, identifier.span()).0)
+        {
+            return *binder;
+        }
+        let (mut binder, _) = self.fresh_variable(true, identifier.span());
+        binder.annotation = identifier.annotation().map(TokenExt::span);
+        *self.identifiers.last_mut().unwrap() = binder;
+        binder
     }
 
     fn parse_atom(&mut self, atom: &Token) -> Option<Atom<B>> {
         match atom.kind() {
             Kind::String => Atom::String {
                 source: atom.span(),
-                value:  {
-                    let text = atom.text();
-                    text[3..text.len() - 3].to_string()
-                },
+                value:  unquote(atom.text(), &[atom.text()]).map_or_else(
+                    |message| {
+                        self.error(atom.span(), message);
+                        String::new()
+                    },
+                    |mut parts| parts.remove(0),
+                ),
             },
             Kind::Number => Atom::Number {
                 source: atom.span(),
-                value:  atom.text().parse().expect("Could not parse number."),
+                value:  atom.text().parse().unwrap_or_else(|message| {
+                    self.error(atom.span(), format!("Invalid number: {message}."));
+                    0_u64.into()
+                }),
             },
             Kind::Identifier => {
                 if let Some(binder) = atom.resolve() {
@@ -216,7 +594,12 @@ impl<B, F: FnMut(&str) -> Option<B>> Compiler<B, F> {
                         builtin,
                     }
                 } else {
-                    panic!("Could not resolve identifier `{}`", atom.text());
+                    let message = format!("Unknown identifier `{}`.", atom.text());
+                    self.error(atom.span(), message);
+                    Atom::String {
+                        source: atom.span(),
+                        value:  String::new(),
+                    }
                 }
             }
             _ => return None,
@@ -224,12 +607,66 @@ impl<B, F: FnMut(&str) -> Option<B>> Compiler<B, F> {
         .into()
     }
 
+    fn error(&mut self, source: Span, message: String) {
+        self.errors.push(CompileError { source, message });
+    }
+
     /// Construct a fresh name for an anonymous expression.
     fn fresh_variable(&mut self, named: bool, source: Span) -> (Identifier, Atom<B>) {
         let id = self.identifiers.len() as u32;
-        let identifier = Identifier { source, named, id };
+        let identifier = Identifier {
+            source,
+            named,
+            id,
+            annotation: None,
+        };
         let atom = Atom::Reference { source, id };
         self.identifiers.push(identifier);
         (identifier, atom)
     }
 }
+
+/// Whether a loose call is a test, i.e. calls `test` which is not defined.
+fn is_test(node: &Node) -> bool {
+    node.children_with_tokens()
+        .filter_map(ElementRef::into_token)
+        .find(|t| t.kind() == Kind::Identifier)
+        .is_some_and(|t| t.text() == "test" && t.resolve().is_none())
+}
+
+/// Name of a data declaration or constructor, its first identifier.
+fn name(node: &Node) -> &str {
+    node.children_with_tokens()
+        .filter_map(ElementRef::into_token)
+        .find(|t| t.kind() == Kind::Identifier)
+        .map_or("", Token::text)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{builtins::Builtin, front::parse},
+    };
+
+    fn errors(source: &str) -> Vec<String> {
+        let root = parse(source);
+        compile(source.to_string(), &root, Builtin::resolve)
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_constructors() {
+        assert_eq!(errors("data Bool = true | false\n"), Vec::<String>::new());
+        assert_eq!(errors("data Bool = true | true\n"), [
+            "Duplicate constructor `true`."
+        ]);
+        assert_eq!(errors("data A = x\ndata B = y | x\n"), [
+            "Duplicate constructor `x`."
+        ]);
+    }
+}
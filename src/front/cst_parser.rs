@@ -19,7 +19,7 @@ pub(super) type CstInput<'s> =
     MappedInput<Kind, Span, Stream<Lexer<'s>>, fn((Kind, Span)) -> (Kind, Span)>;
 pub(super) type CstCursor<'s, 'a> = Cursor<'s, 'a, CstInput<'s>>;
 pub(super) type CstCheckpoint<'s, 'a> =
-    chumsky::input::Checkpoint<'s, 'a, CstInput<'s>, Checkpoint>;
+    chumsky::input::Checkpoint<'s, 'a, CstInput<'s>, (Checkpoint, usize)>;
 
 pub(super) trait CstParser<'s, 'c: 's, Output = ()>:
     Parser<'s, CstInput<'s>, Output, CstExtra<'s, 'c>>
@@ -31,6 +31,8 @@ impl<'s, 'c: 's, O, P: Parser<'s, CstInput<'s>, O, CstExtra<'s, 'c>>> CstParser<
 pub(super) struct CstState<'s, 'c> {
     pub(super) source:  &'s str,
     pub(super) builder: GreenNodeBuilder<'c, 'c, Kind>,
+    /// End of the source text in the CST so far.
+    pub(super) end:     usize,
 }
 
 /// Generate a `GreenToken` from a parser that outputs the token kind.
@@ -67,18 +69,36 @@ pub(super) fn token<'s, 'c: 's>(node: Kind) -> impl CstParser<'s, 'c> + Clone {
     })
 }
 
+/// An identifier. The keywords and separators of declarations are names too
+/// where the grammar does not expect them, and become `Identifier` tokens.
+pub(super) fn name<'s, 'c: 's>() -> impl CstParser<'s, 'c> + Clone {
+    Ext(CstLeafExt {
+        parser:   one_of([
+            Kind::Identifier,
+            Kind::Equals,
+            Kind::Bar,
+            Kind::KeywordData,
+            Kind::KeywordMatch,
+        ])
+        .to(Kind::Identifier),
+        _phantom: PhantomData,
+    })
+}
+
 /// Inspector for the Chumsky parser to build the CST.
 impl<'s, 'c: 's> Inspector<'s, CstInput<'s>> for CstState<'s, 'c> {
-    type Checkpoint = Checkpoint;
+    type Checkpoint = (Checkpoint, usize);
 
     fn on_token(&mut self, _token: &Kind) {}
 
-    fn on_save<'parse>(&self, _cursor: &CstCursor<'s, 'parse>) -> Checkpoint {
-        self.builder.checkpoint()
+    fn on_save<'parse>(&self, _cursor: &CstCursor<'s, 'parse>) -> (Checkpoint, usize) {
+        (self.builder.checkpoint(), self.end)
     }
 
     fn on_rewind<'parse>(&mut self, marker: &CstCheckpoint<'s, 'parse>) {
-        self.builder.revert_to(*marker.inspector());
+        let (checkpoint, end) = *marker.inspector();
+        self.builder.revert_to(checkpoint);
+        self.end = end;
     }
 }
 
@@ -95,6 +115,7 @@ impl<'s, 'c: 's, P: CstParser<'s, 'c, Kind>> ExtParser<'s, CstInput<'s>, (), Cst
         let span = inp.span_since(&before);
         let text = &inp.state().source[span.start..span.end];
         inp.state().builder.token(node, text);
+        inp.state().end = span.end;
         Ok(())
     }
 }
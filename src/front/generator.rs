@@ -0,0 +1,486 @@
+//! Random programs for fuzzing the front end and the optimisation passes.
+
+use core::fmt::Write;
+
+/// Identifiers, including builtins, keywords of the compiler and symbols.
+const IDENTIFIERS: &[&str] = &[
+    "x",
+    "y",
+    "f",
+    "g",
+    "ret",
+    "done",
+    "main",
+    "test",
+    "add",
+    "mul",
+    "print",
+    "show",
+    "concat",
+    "assert_eq",
+    "Oluś",
+    "α",
+    "+",
+    "→",
+    "=",
+    "data",
+    "match",
+];
+
+/// Type annotations, which are not parsed.
+const ANNOTATIONS: &[&str] = &["[Number]", "[String]", "[(Number) → ()]", "[[nested]]"];
+
+/// Numbers, including one that does not fit a machine integer.
+const NUMBERS: &[&str] = &[
+    "0",
+    "-12",
+    "1.5",
+    "2e3",
+    "-7.25e-2",
+    "123456789012345678901234567890",
+];
+
+/// Contents of string literals and parts of interpolated strings.
+const TEXTS: &[&str] = &[
+    "",
+    "hello",
+    "a “nested” string",
+    "an escaped \\” quote",
+    "tab\\tand newline\\n",
+    "\\u{1F600}",
+    "Oluś",
+];
+
+const TYPES: &[&str] = &["Option", "List", "Pair"];
+
+const CONSTRUCTORS: &[&str] = &["Some", "None", "Cons", "Nil", "Pair"];
+
+/// Start of every runnable program, a data type and a match on it.
+const PRELUDE: &str = "data Maybe = nothing | just value
+
+unwrap m default ret:
+    match m
+    | nothing: ret default
+    | just v: ret v
+";
+
+/// Generator of random programs.
+///
+/// [`Self::program`] generates programs that parse without syntax errors, most
+/// of which do not compile as names are picked at random. [`Self::runnable`]
+/// generates programs that compile and run.
+///
+/// Choices are read from the given bytes and then from a pseudo random
+/// sequence, so mutating the input of a fuzzer mutates the program.
+pub struct Generator {
+    bytes:    Vec<u8>,
+    position: usize,
+    state:    u64,
+}
+
+/// A procedure `name parameter… ret` of a runnable program that returns a
+/// number.
+struct Function {
+    name:       String,
+    parameters: usize,
+    /// Takes a unary function as its first parameter.
+    higher:     bool,
+    /// Recurses on its only parameter, which must be small and not negative.
+    recursive:  bool,
+}
+
+impl Generator {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            bytes:    Vec::new(),
+            position: 0,
+            state:    seed,
+        }
+    }
+
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes:    bytes.to_vec(),
+            position: 0,
+            state:    0,
+        }
+    }
+
+    /// Generate a program.
+    pub fn program(&mut self) -> String {
+        let mut source = String::new();
+        self.block(&mut source, 0);
+        source
+    }
+
+    /// Statements at one indentation, each possibly followed by a block.
+    fn block(&mut self, source: &mut String, level: usize) {
+        for _ in 0..=self.choose(4) {
+            if self.choose(8) == 0 {
+                source.push('\n');
+            }
+            source.push_str(&"    ".repeat(level));
+            match self.choose(8) {
+                0 => self.data(source),
+                1..=4 => self.procedure(source, level),
+                _ => self.call(source, level, 0),
+            }
+            source.push('\n');
+            if level < 2 && self.choose(4) == 0 {
+                self.block(source, level + 1);
+            }
+        }
+    }
+
+    /// `data Name = Constructor field… | …`
+    fn data(&mut self, source: &mut String) {
+        source.push_str("data ");
+        source.push_str(self.pick(TYPES));
+        source.push_str(" = ");
+        for i in 0..=self.choose(3) {
+            if i > 0 {
+                source.push_str(" | ");
+            }
+            source.push_str(self.pick(CONSTRUCTORS));
+            for _ in 0..self.choose(3) {
+                source.push(' ');
+                source.push_str(self.pick(IDENTIFIERS));
+            }
+        }
+    }
+
+    /// `name binder…: call`, where the body may also be on the next line.
+    fn procedure(&mut self, source: &mut String, level: usize) {
+        let binders = self.binders(1);
+        source.push_str(&binders);
+        source.push(':');
+        if self.choose(4) > 0 {
+            source.push(' ');
+            self.call(source, level, 0);
+        }
+    }
+
+    /// At least `min` binders with optional type annotations, separated by
+    /// spaces.
+    fn binders(&mut self, min: usize) -> String {
+        let mut binders = Vec::new();
+        for _ in 0..min + self.choose(4) {
+            let mut binder = self.pick(IDENTIFIERS).to_string();
+            if self.choose(4) == 0 {
+                binder.push_str(self.pick(ANNOTATIONS));
+            }
+            binders.push(binder);
+        }
+        binders.join(" ")
+    }
+
+    /// A match or expressions, the body of a procedure or a statement.
+    fn call(&mut self, source: &mut String, level: usize, depth: usize) {
+        if self.choose(8) == 0 {
+            self.matching(source, level, depth);
+        } else {
+            self.expressions(source, depth);
+        }
+    }
+
+    /// Expressions separated by white space.
+    fn expressions(&mut self, source: &mut String, depth: usize) {
+        for i in 0..=self.choose(4) {
+            if i > 0 {
+                source.push_str(self.pick(&[" ", "  ", "\t"]));
+            }
+            self.expression(source, depth);
+        }
+    }
+
+    /// `match value | Constructor binder…: expression…`, with cases of a
+    /// statement possibly on the following lines.
+    fn matching(&mut self, source: &mut String, level: usize, depth: usize) {
+        source.push_str("match ");
+        self.expression(source, depth + 1);
+        for _ in 0..=self.choose(3) {
+            if depth == 0 && self.choose(3) == 0 {
+                source.push('\n');
+                source.push_str(&"    ".repeat(level));
+            } else {
+                source.push(' ');
+            }
+            source.push_str("| ");
+            source.push_str(self.pick(CONSTRUCTORS));
+            source.push(' ');
+            source.push_str(&self.binders(0));
+            source.push_str(": ");
+            self.expressions(source, depth + 1);
+        }
+    }
+
+    fn expression(&mut self, source: &mut String, depth: usize) {
+        let choice = if depth < 3 {
+            self.choose(9)
+        } else {
+            self.choose(4)
+        };
+        match choice {
+            0 | 1 => source.push_str(self.pick(IDENTIFIERS)),
+            2 => source.push_str(self.pick(NUMBERS)),
+            3 => {
+                source.push('“');
+                source.push_str(self.pick(TEXTS));
+                source.push('”');
+            }
+            4 | 5 => {
+                source.push('(');
+                self.expressions(source, depth + 1);
+                source.push(')');
+            }
+            6 | 7 => {
+                source.push('(');
+                source.push_str(&self.binders(0));
+                source.push(':');
+                if self.choose(4) > 0 {
+                    source.push(' ');
+                    self.call(source, 0, depth + 1);
+                }
+                source.push(')');
+            }
+            _ => {
+                source.push('“');
+                source.push_str(self.pick(TEXTS));
+                for _ in 0..=self.choose(2) {
+                    source.push_str("\\(");
+                    self.expressions(source, depth + 1);
+                    source.push(')');
+                    source.push_str(self.pick(TEXTS));
+                }
+                source.push('”');
+            }
+        }
+    }
+
+    /// Generate a program whose `main` computes with numbers, strings,
+    /// closures, recursion and a data type, prints and exits.
+    pub fn runnable(&mut self) -> String {
+        let mut source = PRELUDE.to_string();
+        let mut functions = Vec::new();
+        for i in 0..=self.choose(6) {
+            source.push('\n');
+            let function = self.function(&mut source, format!("f{i}"), &functions);
+            functions.push(function);
+        }
+        source.push_str("\nmain exit:\n");
+        let mut variables = Vec::new();
+        let mut maybes = Vec::new();
+        for i in 0..=self.choose(8) {
+            source.push_str("    ");
+            match self.choose(6) {
+                0 => {
+                    let value = self.number(&variables, &maybes, &functions, 0);
+                    write!(source, "print {value} (:)").unwrap();
+                }
+                1 => {
+                    let value = self.number(&variables, &maybes, &functions, 0);
+                    write!(source, "print (concat “v{i} ” (show {value})) (:)").unwrap();
+                }
+                2 => {
+                    if self.choose(3) == 0 {
+                        source.push_str("nothing");
+                    } else {
+                        let value = self.number(&variables, &maybes, &functions, 0);
+                        write!(source, "just {value}").unwrap();
+                    }
+                    write!(source, " (m{i}:)").unwrap();
+                    maybes.push(format!("m{i}"));
+                }
+                _ => {
+                    let value = self.number_call(&variables, &maybes, &functions, 0);
+                    write!(source, "{value} (v{i}:)").unwrap();
+                    variables.push(format!("v{i}"));
+                }
+            }
+            source.push('\n');
+        }
+        let status = if self.choose(4) == 0 {
+            self.number(&variables, &maybes, &functions, 1)
+        } else {
+            "0".to_string()
+        };
+        writeln!(source, "    exit {status}").unwrap();
+        source
+    }
+
+    /// A function that may call the earlier `functions`.
+    fn function(&mut self, source: &mut String, name: String, functions: &[Function]) -> Function {
+        let unary = functions.iter().any(is_unary);
+        let choice = self.choose(if unary { 7 } else { 6 });
+        let parameters = match choice {
+            3 | 4 | 6 => 1,
+            _ => 1 + self.choose(3),
+        };
+        let names = ["a", "b", "c"][..parameters].to_vec();
+        let variables = names.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let number =
+            |this: &mut Self, variables: &[String]| this.number(variables, &[], functions, 1);
+        let header = format!("{name} {} ret:", names.join(" "));
+        match choice {
+            0 => writeln!(source, "{header} ret {}", number(self, &variables)).unwrap(),
+            1 => {
+                let (condition, then, other) = (
+                    number(self, &variables),
+                    number(self, &variables),
+                    number(self, &variables),
+                );
+                writeln!(
+                    source,
+                    "{header} if (is_zero {condition}) (: ret {then}) (: ret {other})"
+                )
+                .unwrap();
+            }
+            2 => {
+                // Continuations on the following lines.
+                source.push_str(&header);
+                source.push('\n');
+                let mut variables = variables;
+                for i in 0..=self.choose(3) {
+                    let value = self.number_call(&variables, &[], functions, 1);
+                    writeln!(source, "    {value} (s{i}:)").unwrap();
+                    variables.push(format!("s{i}"));
+                }
+                writeln!(source, "    ret {}", number(self, &variables)).unwrap();
+            }
+            3 => {
+                let base = number(self, &variables);
+                let step = number(self, &["a".to_string(), "r".to_string()]);
+                writeln!(
+                    source,
+                    "{header} if (is_zero a) (: ret {base}) (: {name} (sub a 1) (r: ret {step}))"
+                )
+                .unwrap();
+                return Function {
+                    name,
+                    parameters,
+                    higher: false,
+                    recursive: true,
+                };
+            }
+            4 => {
+                let value = number(self, &variables);
+                writeln!(source, "{header} print a (: ret {value})").unwrap();
+            }
+            6 => {
+                writeln!(source, "{name} g a ret: g a (b: g b ret)").unwrap();
+                return Function {
+                    name,
+                    parameters,
+                    higher: true,
+                    recursive: false,
+                };
+            }
+            _ => {
+                // Forward to a local procedure.
+                let value = number(self, &variables);
+                writeln!(source, "{header}\n    local\n    local: ret {value}").unwrap();
+            }
+        }
+        Function {
+            name,
+            parameters,
+            higher: false,
+            recursive: false,
+        }
+    }
+
+    /// A call that returns a number, without its continuation.
+    fn number_call(
+        &mut self,
+        variables: &[String],
+        maybes: &[String],
+        functions: &[Function],
+        depth: usize,
+    ) -> String {
+        let callable = functions
+            .iter()
+            .filter(|f| !f.higher || functions.iter().any(is_unary))
+            .collect::<Vec<_>>();
+        if !maybes.is_empty() && self.choose(4) == 0 {
+            let maybe = &maybes[self.choose(maybes.len())];
+            let default = self.number(variables, maybes, functions, depth + 1);
+            return format!("unwrap {maybe} {default}");
+        }
+        if callable.is_empty() || self.choose(3) == 0 {
+            let operation = self.pick(&["add", "sub", "mul", "rem"]);
+            let left = self.number(variables, maybes, functions, depth + 1);
+            let right = if operation == "rem" {
+                (1 + self.choose(9)).to_string()
+            } else {
+                self.number(variables, maybes, functions, depth + 1)
+            };
+            return format!("{operation} {left} {right}");
+        }
+        let function = callable[self.choose(callable.len())];
+        let mut call = function.name.clone();
+        if function.recursive {
+            write!(call, " {}", self.choose(6)).unwrap();
+        } else if function.higher {
+            let unary = functions.iter().filter(|f| is_unary(f)).collect::<Vec<_>>();
+            let argument = self.number(variables, maybes, functions, depth + 1);
+            write!(call, " {} {argument}", unary[self.choose(unary.len())].name).unwrap();
+        } else {
+            for _ in 0..function.parameters {
+                let argument = self.number(variables, maybes, functions, depth + 1);
+                call.push(' ');
+                call.push_str(&argument);
+            }
+        }
+        call
+    }
+
+    /// An expression that evaluates to a number.
+    fn number(
+        &mut self,
+        variables: &[String],
+        maybes: &[String],
+        functions: &[Function],
+        depth: usize,
+    ) -> String {
+        match self.choose(if depth < 3 { 6 } else { 3 }) {
+            0 if !variables.is_empty() => variables[self.choose(variables.len())].clone(),
+            0 | 1 => self.choose(11).to_string(),
+            2 => format!("-{}", 1 + self.choose(5)),
+            _ => format!(
+                "({})",
+                self.number_call(variables, maybes, functions, depth)
+            ),
+        }
+    }
+
+    fn pick<'a>(&mut self, options: &[&'a str]) -> &'a str {
+        options[self.choose(options.len())]
+    }
+
+    /// A choice out of `n`, at most 256.
+    fn choose(&mut self, n: usize) -> usize {
+        let byte = if let Some(&byte) = self.bytes.get(self.position) {
+            self.position += 1;
+            byte
+        } else {
+            self.next().to_le_bytes()[0]
+        };
+        usize::from(byte) % n
+    }
+
+    /// Next value of a `SplitMix64` sequence.
+    const fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Whether a function takes a number and returns a number.
+const fn is_unary(function: &Function) -> bool {
+    function.parameters == 1 && !function.higher && !function.recursive
+}
@@ -1,7 +1,7 @@
 use {
     super::{
         Kind,
-        cst_parser::{CstParser, ParserExt, token},
+        cst_parser::{CstParser, ParserExt, name, token},
     },
     chumsky::prelude::*,
 };
@@ -12,8 +12,11 @@ pub(super) fn parser<'source, 'cache: 'source>() -> impl CstParser<'source, 'cac
     #[allow(clippy::enum_glob_use)]
     use Kind::*;
 
+    // A binder with an optional type annotation.
+    let binder = name().then_ignore(token(Annotation).or_not());
+
     let expression = recursive(|expression| {
-        let atom = choice((token(Identifier), token(Number), token(String)));
+        let atom = choice((name(), token(Number), token(String)));
 
         let call = expression
             .clone()
@@ -24,38 +27,82 @@ pub(super) fn parser<'source, 'cache: 'source>() -> impl CstParser<'source, 'cac
             .delimited_by(token(ParenOpen), token(ParenClose))
             .node(Call);
 
-        let procedure = token(Identifier)
+        // `“text \(expression…) text”`
+        let segment = expression
+            .clone()
+            .then_ignore(token(Whitespace).or_not())
+            .repeated()
+            .at_least(1)
+            .padded_by(token(Whitespace).or_not())
+            .node(Call);
+        let interpolation = token(StringStart)
+            .then_ignore(segment.clone())
+            .then_ignore(token(StringMiddle).then_ignore(segment).repeated())
+            .then_ignore(token(StringEnd))
+            .node(Interpolation);
+
+        let procedure = binder
+            .clone()
             .separated_by(token(Whitespace).or_not())
             .then_ignore(token(Colon).padded_by(token(Whitespace).or_not()))
             .then_ignore(
-                expression
-                    .then_ignore(token(Whitespace).or_not())
-                    .repeated()
-                    .at_least(1)
-                    .node(Call)
-                    .or_not(),
+                choice((
+                    matching(expression.clone()),
+                    expression
+                        .then_ignore(token(Whitespace).or_not())
+                        .repeated()
+                        .at_least(1),
+                ))
+                .node(Call)
+                .or_not(),
             )
             .delimited_by(token(ParenOpen), token(ParenClose))
             .node(Proc);
 
-        choice((atom, call, procedure))
+        choice((atom, call, procedure, interpolation))
     });
 
-    let call = expression
-        .separated_by(token(Whitespace).or_not())
-        .at_least(1)
-        .then_ignore(token(Newline))
-        .node(Call);
+    let call = choice((
+        matching(expression.clone()),
+        expression
+            .clone()
+            .separated_by(token(Whitespace).or_not())
+            .at_least(1),
+    ))
+    .then_ignore(token(Newline))
+    .node(Call);
 
-    let procedure = token(Identifier)
+    let procedure = binder
         .separated_by(token(Whitespace).or_not())
         .at_least(1)
         .then_ignore(token(Colon).padded_by(token(Whitespace).or_not()))
         .then_ignore(call.clone().or(token(Newline)))
         .node(Proc);
 
+    // `data Name = constructor field… | …`
+    let constructor = token(Identifier)
+        .separated_by(token(Whitespace).or_not())
+        .at_least(1)
+        .node(Constructor);
+    let data = token(KeywordData)
+        .then_ignore(token(Whitespace).or_not())
+        .then_ignore(token(Identifier))
+        .then_ignore(token(Equals).padded_by(token(Whitespace).or_not()))
+        .then_ignore(
+            constructor
+                .separated_by(token(Bar).padded_by(token(Whitespace).or_not()))
+                .at_least(1),
+        )
+        .then_ignore(token(Newline))
+        .node(Data);
+
+    // A match is tried before a procedure, whose binders could be its start.
+    let match_call = matching(expression).then_ignore(token(Newline)).node(Call);
+
     let block = recursive(|block| {
         choice((
+            data,
+            match_call,
             procedure,
             call,
             block.delimited_by(token(Indent), token(Dedent)).node(Block),
@@ -65,3 +112,92 @@ pub(super) fn parser<'source, 'cache: 'source>() -> impl CstParser<'source, 'cac
 
     block.padded_by(token(Newline).or_not())
 }
+
+/// `match value | constructor binder…: call | …` where cases may also start on
+/// the following lines at the same indentation.
+fn matching<'source, 'cache: 'source>(
+    expression: impl CstParser<'source, 'cache> + Clone,
+) -> impl CstParser<'source, 'cache> + Clone {
+    #[allow(clippy::enum_glob_use)]
+    use Kind::*;
+
+    let binder = name().then_ignore(token(Annotation).or_not());
+    // A `|` in a case body starts the next case.
+    let body = just(Bar)
+        .not()
+        .ignore_then(expression.clone())
+        .then_ignore(token(Whitespace).or_not())
+        .repeated()
+        .at_least(1)
+        .node(Call);
+    let case = token(Bar)
+        .then_ignore(token(Whitespace).or_not())
+        .then_ignore(token(Identifier))
+        .then_ignore(token(Whitespace).or_not())
+        .then_ignore(
+            binder
+                .separated_by(token(Whitespace).or_not())
+                .then_ignore(token(Colon).padded_by(token(Whitespace).or_not()))
+                .then_ignore(body)
+                .node(Proc),
+        )
+        .node(Case);
+
+    token(KeywordMatch)
+        .then_ignore(token(Whitespace).or_not())
+        .then_ignore(expression)
+        .then_ignore(token(Whitespace).or_not())
+        .then_ignore(
+            token(Newline)
+                .or_not()
+                .then_ignore(case)
+                .repeated()
+                .at_least(1),
+        )
+        .node(Match)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        front::{Kind, format_cst, parse},
+        ir::format_procedure,
+        tests::program,
+    };
+
+    #[test]
+    fn keywords_as_names() {
+        let source = "= a b ret: ret a\n| a ret: ret a\ndata ret: ret 1\nmatch x ret: ret x\nmain \
+                      exit: data (x: = x 2 (y: | y exit))\n";
+        let cst = format_cst(&parse(source));
+        assert!(!cst.contains("Error"), "{cst}");
+        let program = program(source);
+        let main = program.procedure_by_name("main").unwrap();
+        assert_eq!(
+            format_procedure(&program, main),
+            "main_12 exit_13: data_7 _16\n"
+        );
+        for name in ["=", "|", "data", "match"] {
+            assert!(program.procedure_by_name(name).is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn declarations() {
+        let source = "data Nat = zero | succ n\nf n ret: match n | zero: ret 0 | succ m: f m ret\n";
+        let cst = format_cst(&parse(source));
+        let nodes = |kind: Kind| {
+            cst.lines()
+                .filter(|line| line.split_whitespace().nth(1) == Some(&kind.to_string()))
+                .count()
+        };
+        assert_eq!((nodes(Kind::Data), nodes(Kind::Case)), (1, 2), "{cst}");
+
+        // A match on the following line is not a procedure named `match`.
+        let source = "data Nat = zero | succ n\nf n ret:\n    match n | zero: ret 0\n    | succ \
+                      m: f m ret\n";
+        let cst = format_cst(&parse(source));
+        assert!(!cst.contains("Error"), "{cst}");
+        assert_eq!((nodes(Kind::Proc), nodes(Kind::Case)), (3, 2), "{cst}");
+    }
+}
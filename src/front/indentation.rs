@@ -2,6 +2,7 @@
 //! tokenizing  allows the grammar to be context-free.
 use {
     super::{Kind, Span},
+    core::mem::take,
     logos::{Lexer as LogosLexer, Logos},
 };
 
@@ -45,6 +46,13 @@ impl Iterator for Lexer<'_> {
             .unwrap_or_else(|err| err.unwrap_or(Kind::ErrorUnknownToken));
         let span = self.logos.span().into();
 
+        // Replace interpolated strings by their parts and the tokens in between.
+        if token == Kind::String && !self.logos.extras.is_empty() {
+            self.buffer = take(&mut self.logos.extras);
+            self.buffer.reverse();
+            return self.buffer.pop();
+        }
+
         // Pass through non indentation tokens
         if token != Kind::Newline {
             return Some((token, span));
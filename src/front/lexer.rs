@@ -1,7 +1,11 @@
 //! Lexer based on Logos. For convenience the `Node` enum contains both tokens
 //! and grammar nodes.
 use {
-    core::fmt::{self, Display},
+    super::Span,
+    core::{
+        fmt::{self, Display},
+        mem::take,
+    },
     cstree::{RawSyntaxKind, Syntax},
     logos::Logos,
     num_enum::{IntoPrimitive, TryFromPrimitive},
@@ -12,6 +16,7 @@ use {
     Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Logos, IntoPrimitive, TryFromPrimitive,
 )]
 #[logos(error = Option<Kind>)]
+#[logos(extras = Vec<(Kind, Span)>)]
 #[repr(u8)]
 pub enum Kind {
     /// White space without line breaks
@@ -37,6 +42,18 @@ pub enum Kind {
     /// Closing Parenthesis
     #[token(")")]
     ParenClose,
+    /// Equals sign
+    #[token("=")]
+    Equals,
+    /// Vertical bar separating alternatives
+    #[token("|")]
+    Bar,
+    /// Keyword starting a data type declaration
+    #[token("data")]
+    KeywordData,
+    /// Keyword starting a match
+    #[token("match")]
+    KeywordMatch,
 
     /// Identifiers and symbols
     /// See <https://www.unicode.org/reports/tr31>
@@ -47,18 +64,33 @@ pub enum Kind {
     /// Strings litteral.
     /// Strings are delimited by mirrored assymetric double qoutes: “ and ”.
     /// Nested strings are recognized and considered a single string.
+    /// A backslash escapes the next character, so `\”` does not close it.
+    /// Strings with interpolations `\(…)` are split into [`Self::StringStart`],
+    /// [`Self::StringMiddle`] and [`Self::StringEnd`] by the indentation lexer.
     #[token("“", string)]
     String,
 
-    /// Number literal
-    #[regex(r"[0-9]+")]
+    /// Number literal: an integer like `-12` or a float like `1.5e-3`.
+    #[regex(r"-?[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?")]
     Number,
 
+    /// Type annotation following a binder, delimited by `[` and `]`.
+    /// Nested brackets are recognized and considered a single annotation.
+    /// A `[` that does not directly follow a binder is an identifier.
+    #[token("[", annotation)]
+    Annotation,
+
     // Virtual tokens to make the grammer context free.
     /// Increased indentation.
     Indent,
     /// Decreased indentation.
     Dedent,
+    /// Interpolated string up to the first interpolation, `“…\(`.
+    StringStart,
+    /// Interpolated string between interpolations, `)…\(`.
+    StringMiddle,
+    /// Interpolated string after the last interpolation, `)…”`.
+    StringEnd,
 
     // Grammar nodes
     /// An indented block of code
@@ -67,16 +99,30 @@ pub enum Kind {
     Proc,
     /// A fragment specifying a call
     Call,
+    /// A data type declaration
+    Data,
+    /// A constructor in a data type declaration
+    Constructor,
+    /// A match on a data type value
+    Match,
+    /// A case in a match
+    Case,
+    /// A string with interpolated expressions
+    Interpolation,
 
     // Errors
     /// Unknown token.
     ErrorUnknownToken,
     /// Unterminated string literal.
     ErrorUnterminatedString,
+    /// Unterminated type annotation.
+    ErrorUnterminatedAnnotation,
     /// Inconsistent indentation.
     ErrorInconsistentIndentation,
     /// Invalid token kind.
     ErrorInvalidTokenKind,
+    /// Source text after a syntax error.
+    ErrorSyntax,
 }
 
 impl Kind {
@@ -85,7 +131,35 @@ impl Kind {
     pub const fn is_syntax(&self) -> bool {
         matches!(
             self,
-            Self::Block | Self::Proc | Self::Call | Self::Identifier | Self::String | Self::Number
+            Self::Block
+                | Self::Proc
+                | Self::Call
+                | Self::Data
+                | Self::Constructor
+                | Self::Match
+                | Self::Case
+                | Self::Interpolation
+                | Self::Identifier
+                | Self::String
+                | Self::StringStart
+                | Self::StringMiddle
+                | Self::StringEnd
+                | Self::Number
+                | Self::Annotation
+        )
+    }
+
+    /// Tokens marking errors in the source.
+    #[must_use]
+    pub const fn is_error(&self) -> bool {
+        matches!(
+            self,
+            Self::ErrorUnknownToken
+                | Self::ErrorUnterminatedString
+                | Self::ErrorUnterminatedAnnotation
+                | Self::ErrorInconsistentIndentation
+                | Self::ErrorInvalidTokenKind
+                | Self::ErrorSyntax
         )
     }
 }
@@ -114,6 +188,10 @@ impl Syntax for Kind {
             Self::Colon => ":",
             Self::ParenOpen => "(",
             Self::ParenClose => ")",
+            Self::Equals => "=",
+            Self::Bar => "|",
+            Self::KeywordData => "data",
+            Self::KeywordMatch => "match",
             Self::Dedent => "",
             _ => {
                 return None;
@@ -123,32 +201,151 @@ impl Syntax for Kind {
     }
 }
 
-/// Matches a string literal.
+/// Matches a string literal. The parts and tokens of an interpolated string
+/// are left in `lexer.extras`.
 fn string(lexer: &mut logos::Lexer<Kind>) -> Result<(), Option<Kind>> {
-    #[derive(Logos)]
-    #[logos(skip "[^“”]+")]
-    enum Token {
-        #[token("“")]
-        Open,
-        #[token("”")]
-        Close,
+    let mut parts = Vec::new();
+    let length = string_parts(lexer.remainder(), lexer.span().start, &mut parts)
+        .ok_or(Some(Kind::ErrorUnterminatedString))?;
+    lexer.bump(length);
+    lexer.extras = parts;
+    Ok(())
+}
+
+/// Length of a string literal after the opening quote at `start`. If the
+/// string is interpolated its parts and the tokens in between are added to
+/// `parts`.
+fn string_parts(text: &str, start: usize, parts: &mut Vec<(Kind, Span)>) -> Option<usize> {
+    let offset = start + '“'.len_utf8();
+    let mut part = start;
+    let mut nesting = 1;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        i += c.len_utf8();
+        match c {
+            '“' => nesting += 1,
+            '”' => {
+                nesting -= 1;
+                if nesting == 0 {
+                    if !parts.is_empty() {
+                        parts.push((Kind::StringEnd, Span::new(part, offset + i)));
+                    }
+                    return Some(i);
+                }
+            }
+            '\\' if text[i..].starts_with('(') => {
+                i += 1;
+                let kind = if parts.is_empty() {
+                    Kind::StringStart
+                } else {
+                    Kind::StringMiddle
+                };
+                parts.push((kind, Span::new(part, offset + i)));
+                i += interpolation(&text[i..], offset + i, parts)?;
+                part = offset + i;
+            }
+            // Escaped characters do not affect nesting.
+            '\\' => i += text[i..].chars().next().map_or(0, char::len_utf8),
+            _ => {}
+        }
+    }
+
+    // Unclosed string literal
+    None
+}
+
+/// Length of an interpolation up to its closing parenthesis. The tokens are
+/// added to `parts`, with newlines as whitespace.
+fn interpolation(text: &str, offset: usize, parts: &mut Vec<(Kind, Span)>) -> Option<usize> {
+    let shift =
+        |(kind, span): (Kind, Span)| (kind, Span::new(offset + span.start, offset + span.end));
+    let mut lexer = Kind::lexer(text);
+    let mut depth = 0_usize;
+    while let Some(token) = lexer.next() {
+        let kind = token.unwrap_or_else(|err| err.unwrap_or(Kind::ErrorUnknownToken));
+        let span = lexer.span();
+        match kind {
+            Kind::ParenClose if depth == 0 => return Some(span.start),
+            Kind::ParenClose => depth -= 1,
+            Kind::ParenOpen => depth += 1,
+            Kind::ErrorUnterminatedString => return None,
+            Kind::String if !lexer.extras.is_empty() => {
+                parts.extend(take(&mut lexer.extras).into_iter().map(shift));
+                continue;
+            }
+            _ => {}
+        }
+        let kind = if kind == Kind::Newline {
+            Kind::Whitespace
+        } else {
+            kind
+        };
+        parts.push(shift((kind, span.into())));
+    }
+    None
+}
+
+/// Matches a type annotation. Without a binder directly before it the `[` is
+/// the symbol identifier, which is passed as the error kind.
+fn annotation(lexer: &mut logos::Lexer<Kind>) -> Result<(), Option<Kind>> {
+    let before = lexer.source()[..lexer.span().start].chars().next_back();
+    if before.is_none_or(|c| c.is_whitespace() || c == '(') {
+        return Err(Some(Kind::Identifier));
     }
-    let inner = Token::lexer(lexer.remainder());
     let mut nesting = 1;
-    for (token, span) in inner.spanned() {
-        match token {
-            Ok(Token::Open) => nesting += 1,
-            Ok(Token::Close) => {
+    for (i, c) in lexer.remainder().char_indices() {
+        match c {
+            '[' => nesting += 1,
+            ']' => {
                 nesting -= 1;
                 if nesting == 0 {
-                    lexer.bump(span.end);
+                    lexer.bump(i + 1);
                     return Ok(());
                 }
             }
-            Err(()) => unreachable!("String parser is infallible."),
+            '\n' => break,
+            _ => {}
         }
     }
 
-    // Unclosed string literal
-    Err(Some(Kind::ErrorUnterminatedString))
+    // Unclosed annotation
+    Err(Some(Kind::ErrorUnterminatedAnnotation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<Kind> {
+        Kind::lexer(source)
+            .map(|token| token.unwrap_or_else(|err| err.unwrap_or(Kind::ErrorUnknownToken)))
+            .collect()
+    }
+
+    #[test]
+    fn annotation_after_binder() {
+        assert_eq!(kinds("n[Nat]"), [Kind::Identifier, Kind::Annotation]);
+        assert_eq!(kinds("n[a"), [
+            Kind::Identifier,
+            Kind::ErrorUnterminatedAnnotation,
+            Kind::Identifier,
+        ]);
+    }
+
+    #[test]
+    fn bracket_symbol() {
+        assert_eq!(kinds("["), [Kind::Identifier]);
+        assert_eq!(kinds("f [ x"), [
+            Kind::Identifier,
+            Kind::Whitespace,
+            Kind::Identifier,
+            Kind::Whitespace,
+            Kind::Identifier,
+        ]);
+        assert_eq!(kinds("([)"), [
+            Kind::ParenOpen,
+            Kind::Identifier,
+            Kind::ParenClose
+        ]);
+    }
 }
@@ -3,14 +3,17 @@
 
 mod compiler;
 mod cst_parser;
+mod generator;
 mod grammar;
 mod indentation;
 mod lexer;
+mod string;
 mod syntax;
 
 pub use {
     self::{
-        compiler::compile,
+        compiler::{CompileError, compile},
+        generator::Generator,
         lexer::Kind,
         syntax::{NodeExt, TokenExt},
     },
@@ -27,6 +30,7 @@ use {
         Parser,
         input::{Input, Stream},
     },
+    core::fmt,
     cstree::{
         build::GreenNodeBuilder,
         syntax::{ResolvedElement, ResolvedElementRef, ResolvedNode, ResolvedToken, SyntaxNode},
@@ -50,7 +54,11 @@ pub fn parse(source: &str) -> Node {
 
     // Construct a builder to build the CST.
     let builder = GreenNodeBuilder::<Kind>::new();
-    let mut state = CstState { source, builder };
+    let mut state = CstState {
+        source,
+        builder,
+        end: 0,
+    };
     state.builder.start_node(Kind::Block); // Root node is a block
 
     // Parse the source and print errors.
@@ -76,6 +84,11 @@ pub fn parse(source: &str) -> Node {
         }
     }
 
+    // Keep what did not parse, so the tree covers all of the source.
+    if state.end < source.len() {
+        state.builder.token(Kind::ErrorSyntax, &source[state.end..]);
+    }
+
     // Complete and retrieve the root node.
     state.builder.finish_node();
     let (root, node_cache) = state.builder.finish();
@@ -85,18 +98,63 @@ pub fn parse(source: &str) -> Node {
     SyntaxNode::new_root_with_resolver(root, interner)
 }
 
+/// Whether `source` is a complete input for an interactive session.
+///
+/// Complete input has no open parentheses or strings and does not end in a
+/// block that may continue on the next line. An indented block is ended by an
+/// empty line.
+#[must_use]
+pub fn is_complete(source: &str) -> bool {
+    let mut parentheses = 0_usize;
+    let mut indentation = 0_usize;
+    let mut last = None;
+    for (kind, span) in Lexer::new(source) {
+        match kind {
+            Kind::ParenOpen => parentheses += 1,
+            Kind::ParenClose => parentheses = parentheses.saturating_sub(1),
+            Kind::Indent => indentation += 1,
+            // Dedents of the final newline do not close the block yet.
+            Kind::Dedent if span.start < source.len() => {
+                indentation = indentation.saturating_sub(1);
+            }
+            Kind::ErrorUnterminatedString => return false,
+            _ => {}
+        }
+        if kind.is_syntax() || kind == Kind::Colon {
+            last = Some(kind);
+        }
+    }
+    let empty_line = source.trim_end_matches([' ', '\t']).ends_with("\n\n");
+    parentheses == 0 && last != Some(Kind::Colon) && (indentation == 0 || empty_line)
+}
+
 pub fn pretty_print_cst(node: &Node, indent_level: usize) {
+    let mut out = String::new();
+    write_cst(&mut out, node, indent_level).expect("Writing to a string never fails.");
+    eprint!("{out}");
+}
+
+/// The concrete syntax tree as text, one node or token per line.
+#[must_use]
+pub fn format_cst(node: &Node) -> String {
+    let mut out = String::new();
+    write_cst(&mut out, node, 0).expect("Writing to a string never fails.");
+    out
+}
+
+fn write_cst(out: &mut impl fmt::Write, node: &Node, indent_level: usize) -> fmt::Result {
     let indent = "  ".repeat(indent_level);
-    eprint!(
+    write!(
+        out,
         "{:>4}..{:<4}{indent}{:?}",
         usize::from(node.text_range().start()),
         usize::from(node.text_range().end()),
         node.kind()
-    );
+    )?;
     if node.kind() == Kind::Proc {
-        eprint!(" {:?}", node.call());
+        write!(out, " {:?}", node.call())?;
     }
-    eprintln!();
+    writeln!(out)?;
 
     // Recursively print syntax child nodes
     for child in node.children_with_tokens() {
@@ -104,23 +162,25 @@ pub fn pretty_print_cst(node: &Node, indent_level: usize) {
             continue;
         }
         match child {
-            ElementRef::Node(node) => pretty_print_cst(node, indent_level + 1),
+            ElementRef::Node(node) => write_cst(out, node, indent_level + 1)?,
             ElementRef::Token(token) => {
-                eprint!(
+                write!(
+                    out,
                     "{:>4}..{:<4}{indent}  {:?} {:?}",
                     usize::from(token.text_range().start()),
                     usize::from(token.text_range().end()),
                     token.kind(),
                     token.text(),
-                );
+                )?;
                 if token.is_reference() {
-                    eprint!(" {:?}", token.resolve());
+                    write!(out, " {:?}", token.resolve())?;
                 }
                 if token.is_binder() {
-                    eprint!(" BINDER");
+                    write!(out, " BINDER")?;
                 }
-                eprintln!();
+                writeln!(out)?;
             }
         }
     }
+    Ok(())
 }
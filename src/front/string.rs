@@ -0,0 +1,112 @@
+//! Values of string literals.
+//!
+//! A literal that starts with a line break is a block string: the line break
+//! after `“`, the whitespace-only line before `”` and the common indentation
+//! of the non-blank lines are removed. Escapes are processed after that, so
+//! an escaped `\n` is never removed:
+//!
+//! | Escape     | Value               |
+//! |------------|---------------------|
+//! | `\\`       | `\`                 |
+//! | `\“`, `\”` | An unbalanced quote |
+//! | `\n`, `\t` | Newline, tab        |
+//! | `\u{…}`    | Unicode scalar      |
+//! | `\(…)`     | Interpolation       |
+
+/// Values of the text parts of a string literal, given the text of the whole
+/// literal and of its parts including delimiters. A literal without
+/// interpolations is a single part.
+pub(super) fn unquote(text: &str, parts: &[&str]) -> Result<Vec<String>, String> {
+    let block = text.starts_with("“\n") || text.starts_with("“\r\n");
+    let indent = if block { indentation(text) } else { 0 };
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let part = part
+                .strip_prefix('“')
+                .or_else(|| part.strip_prefix(')'))
+                .and_then(|part| part.strip_suffix('”').or_else(|| part.strip_suffix("\\(")))
+                .expect("String part is delimited.");
+            if block {
+                unescape(&dedent(part, indent, i == 0, i == parts.len() - 1))
+            } else {
+                unescape(part)
+            }
+        })
+        .collect()
+}
+
+/// Common indentation of the non-blank lines of a block string.
+fn indentation(text: &str) -> usize {
+    text.lines()
+        .skip(1)
+        .map(|line| line.strip_suffix('”').unwrap_or(line))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0)
+}
+
+fn dedent(part: &str, indent: usize, first: bool, last: bool) -> String {
+    let strip = |line: &'_ str| -> String {
+        let whitespace = line.len() - line.trim_start().len();
+        line[whitespace.min(indent)..].to_string()
+    };
+    let part = if first {
+        part.strip_prefix("\r\n")
+            .or_else(|| part.strip_prefix('\n'))
+            .unwrap_or(part)
+    } else {
+        part
+    };
+    let part = match part.rsplit_once('\n') {
+        Some((body, line)) if last && line.trim().is_empty() => body,
+        _ => part,
+    };
+    let mut lines = part.split('\n');
+    let mut result = lines.next().map_or_else(String::new, |line| {
+        if first { strip(line) } else { line.to_string() }
+    });
+    for line in lines {
+        result.push('\n');
+        result.push_str(&strip(line));
+    }
+    result
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        result.push(match chars.next() {
+            Some(c @ ('\\' | '“' | '”')) => c,
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('u') => {
+                let rest = chars.as_str();
+                let scalar = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok().map(|u| (hex, u)))
+                    .and_then(|(hex, u)| char::from_u32(u).map(|c| (hex, c)));
+                let Some((hex, c)) = scalar else {
+                    return Err(
+                        "Invalid escape `\\u`, expected `\\u{…}` with a hexadecimal Unicode \
+                         scalar value."
+                            .to_string(),
+                    );
+                };
+                chars = rest[hex.len() + 2..].chars();
+                c
+            }
+            Some(c) => return Err(format!("Unknown escape `\\{c}`.")),
+            None => return Err("Unterminated escape `\\`.".to_string()),
+        });
+    }
+    Ok(result)
+}
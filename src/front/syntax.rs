@@ -28,6 +28,9 @@ pub trait TokenExt {
 
     /// Resolve the reference to a binder.
     fn resolve(&self) -> Option<&Token>;
+
+    /// The type annotation of a binder.
+    fn annotation(&self) -> Option<&Token>;
 }
 
 impl NodeExt for Node {
@@ -74,11 +77,21 @@ impl TokenExt for Token {
     }
 
     fn is_binder(&self) -> bool {
-        self.kind() == Kind::Identifier && self.parent().kind() == Kind::Proc
+        self.kind() == Kind::Identifier
+            && match self.parent().kind() {
+                Kind::Proc => true,
+                // Constructor names are binders, their fields are not.
+                Kind::Constructor => self.prev_sibling_or_token().is_none(),
+                _ => false,
+            }
     }
 
     fn is_reference(&self) -> bool {
-        self.kind() == Kind::Identifier && self.parent().kind() != Kind::Proc
+        self.kind() == Kind::Identifier
+            && !matches!(
+                self.parent().kind(),
+                Kind::Proc | Kind::Data | Kind::Constructor
+            )
     }
 
     fn resolve(&self) -> Option<&Token> {
@@ -117,6 +130,15 @@ impl TokenExt for Token {
             scope = scope.parent()?;
         }
     }
+
+    fn annotation(&self) -> Option<&Token> {
+        if !self.is_binder() {
+            return None;
+        }
+        self.next_sibling_or_token()?
+            .into_token()
+            .filter(|t| t.kind() == Kind::Annotation)
+    }
 }
 
 /// First token skipping [`Node::Block`] subtrees.
@@ -0,0 +1,178 @@
+//! Delimited control with effect handlers.
+//!
+//! `handle body handler ret` installs `handler` and calls `body done`. When
+//! the body calls `done x` the handler, and any installed after it, is
+//! removed and `ret x` is called.
+//!
+//! `perform value k` removes the innermost handler and calls
+//! `handler value resume ret`, where `ret` is where its `handle` returns to.
+//! Calling `resume x back` reinstalls the handler, now returning to `back`,
+//! and continues with `k x`. A handler that does not resume aborts the body,
+//! like an exception. A handler may resume more than once.
+
+use {
+    super::Value,
+    std::{mem::swap, rc::Rc},
+};
+
+/// Builtins implemented by the interpreter itself, see also
+/// [`super::Scheduler`].
+pub trait Control {
+    fn control(&self) -> Option<Operator>;
+}
+
+/// Delimited control operators.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Operator {
+    /// `handle body handler ret`
+    Handle,
+    /// `perform value k`
+    Perform,
+    /// `spawn f ret`
+    Spawn,
+    /// `yield k`
+    Yield,
+    /// `channel ret`
+    Channel,
+    /// `send channel value k`
+    Send,
+    /// `recv channel k`
+    Receive,
+    /// `join fiber k`
+    Join,
+}
+
+/// An installed effect handler.
+#[derive(Clone, Debug)]
+pub struct Handler<B> {
+    /// Identifies the `handle` that installed the handler, see
+    /// [`Value::Return`].
+    pub id:      usize,
+    pub handler: Value<B>,
+    /// Continuation receiving the result of the handled body.
+    pub ret:     Value<B>,
+}
+
+/// State of the machine: the current call and the installed handlers,
+/// innermost last.
+#[derive(Clone, Debug)]
+pub struct State<B> {
+    pub call:         Vec<Value<B>>,
+    pub handlers:     Vec<Handler<B>>,
+    /// Id of the next handler installed by `handle`.
+    pub next_handler: usize,
+}
+
+impl<B> State<B> {
+    #[must_use]
+    pub const fn new(call: Vec<Value<B>>) -> Self {
+        Self {
+            call,
+            handlers: Vec::new(),
+            next_handler: 0,
+        }
+    }
+}
+
+impl<B: Clone + Control> State<B> {
+    /// Evaluate the current call if it is a control operator or continuation.
+    /// Returns `false` if it is something else.
+    ///
+    /// # Panics
+    ///
+    /// Panics on `perform` without a handler or when the number of arguments
+    /// is wrong.
+    pub fn control(&mut self) -> bool {
+        let mut body = match &self.call[0] {
+            Value::Builtin(builtin) => match builtin.control() {
+                Some(Operator::Handle) => {
+                    let [_, body, handler, ret] = self.call.as_slice() else {
+                        panic!("`handle` takes three arguments.")
+                    };
+                    let id = self.next_handler;
+                    self.next_handler += 1;
+                    self.handlers.push(Handler {
+                        id,
+                        handler: handler.clone(),
+                        ret: ret.clone(),
+                    });
+                    vec![body.clone(), Value::Return(id)]
+                }
+                Some(Operator::Perform) => {
+                    let [_, value, k] = self.call.as_slice() else {
+                        panic!("`perform` takes two arguments.")
+                    };
+                    let Some(Handler { id, handler, ret }) = self.handlers.pop() else {
+                        panic!("`perform` without a handler.")
+                    };
+                    let resume = Value::Resume(Rc::new((id, handler.clone(), k.clone())));
+                    vec![handler, value.clone(), resume, ret]
+                }
+                _ => return false,
+            },
+            Value::Return(id) => {
+                let [_, value] = self.call.as_slice() else {
+                    panic!("`done` takes one argument.")
+                };
+                // Handlers installed inside the body are removed with it.
+                let index = self
+                    .handlers
+                    .iter()
+                    .rposition(|handler| handler.id == *id)
+                    .expect("`done` is called inside its `handle`.");
+                let ret = self.handlers[index].ret.clone();
+                self.handlers.truncate(index);
+                vec![ret, value.clone()]
+            }
+            Value::Resume(resumption) => {
+                let [_, value, back] = self.call.as_slice() else {
+                    panic!("`resume` takes two arguments.")
+                };
+                let (id, handler, k) = resumption.as_ref();
+                self.handlers.push(Handler {
+                    id:      *id,
+                    handler: handler.clone(),
+                    ret:     back.clone(),
+                });
+                vec![k.clone(), value.clone()]
+            }
+            _ => return false,
+        };
+        swap(&mut self.call, &mut body);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::status;
+
+    #[test]
+    fn resume() {
+        let source = "main exit: handle body handler exit\nbody done: perform 1 (x: done (add x \
+                      10))\nhandler v resume ret: resume (add v 1) ret\n";
+        assert_eq!(status(source), 12);
+    }
+
+    #[test]
+    fn abort() {
+        let source = "main exit: handle body handler exit\nbody done: perform 1 (x: done \
+                      x)\nhandler v resume ret: ret 5\n";
+        assert_eq!(status(source), 5);
+    }
+
+    #[test]
+    fn nested_handlers() {
+        let source = "main exit: handle (doneA: handle (doneB: doneA 1) h (x: exit 2)) h (x: exit \
+                      x)\nh v resume ret: ret 0\n";
+        assert_eq!(status(source), 1);
+    }
+
+    #[test]
+    fn forward_effect() {
+        let source = "main exit: handle outer (v resume ret: resume (mul v 2) ret) exit\nouter \
+                      done: handle inner (v resume ret: perform (add v 1) (x: resume x ret)) \
+                      done\ninner done: perform 20 done\n";
+        assert_eq!(status(source), 42);
+    }
+}
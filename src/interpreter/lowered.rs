@@ -0,0 +1,120 @@
+//! Evaluation of closure converted programs.
+
+use {
+    super::{Control, Scheduler, Value},
+    crate::ir::{Call, Lowered, Operand},
+    core::iter::once,
+    std::{collections::HashMap, fmt::Debug},
+};
+
+/// Like [`super::evaluate`], but for a closure converted program.
+pub fn evaluate_lowered<
+    B: Clone + Debug + Control,
+    R,
+    F: FnMut(&mut Vec<Value<B>>) -> Option<R>,
+>(
+    program: &Lowered<B>,
+    mut builtin: F,
+    call: &[Value<B>],
+) -> R {
+    Scheduler::new(call.to_vec()).run(|state| {
+        if state.control() {
+            return None;
+        }
+        if let Value::Builtin(_) = &state.call[0] {
+            return builtin(&mut state.call);
+        }
+        state.call = iterate_lowered(program, &state.call);
+        None
+    })
+}
+
+/// Evaluate a call to a function, returning the next call.
+fn iterate_lowered<B: Clone>(program: &Lowered<B>, call: &[Value<B>]) -> Vec<Value<B>> {
+    let Value::Closure(id, environment) = &call[0] else {
+        panic!("Can not evaluate non-closure.");
+    };
+    let Some(function) = program.function_by_id(*id) else {
+        panic!("Runtime error: Invalid closure.");
+    };
+    assert_eq!(function.environment.len(), environment.len());
+    assert_eq!(
+        function.lifted.len() + function.parameters.len() + 1,
+        call.len()
+    );
+
+    // Bind the environment record and parameters.
+    let mut locals = function
+        .environment
+        .iter()
+        .copied()
+        .zip(environment.iter().cloned())
+        .chain(
+            function
+                .lifted
+                .iter()
+                .copied()
+                .chain(function.parameters.iter().map(|p| p.id))
+                .zip(call[1..].iter().cloned()),
+        )
+        .collect::<HashMap<_, _>>();
+    let operand = |locals: &HashMap<u32, Value<B>>, operand: &Operand<B>| match operand {
+        Operand::Variable(id) => locals.get(id).cloned().expect("Unresolved variable."),
+        Operand::Current => call[0].clone(),
+        Operand::Builtin(builtin) => Value::Builtin(builtin.clone()),
+        Operand::Number(value) => Value::Number(value.clone()),
+        Operand::String(value) => Value::String(value.clone()),
+    };
+
+    // Allocate closures.
+    for allocation in &function.allocations {
+        let captures = allocation
+            .captures
+            .iter()
+            .map(|c| operand(&locals, c))
+            .collect();
+        locals.insert(
+            allocation.variable,
+            Value::Closure(allocation.function, captures),
+        );
+    }
+
+    match &function.call {
+        Call::Indirect { callee, arguments } => once(callee)
+            .chain(arguments)
+            .map(|a| operand(&locals, a))
+            .collect(),
+        Call::Direct {
+            function,
+            arguments,
+        } => once(Value::Closure(*function, vec![]))
+            .chain(arguments.iter().map(|a| operand(&locals, a)))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            builtins::{Builtin, evaluate},
+            tests::program,
+        },
+    };
+
+    #[test]
+    fn factorial() {
+        let program = program(
+            "fact n return:\n    if (is_zero n) base recurse\n    base: return 1\n    recurse: \
+             return (mul n (fact (sub n 1)))\nmain exit: fact 5 exit\n",
+        );
+        let main = program.procedure_by_name("main").unwrap().id();
+        for lift in [false, true] {
+            let lowered = program.closure_convert(main, lift);
+            let call = [Value::Closure(main, vec![]), Value::Builtin(Builtin::Exit)];
+            let status = evaluate_lowered(&lowered, |call| evaluate(&program, call), &call);
+            assert_eq!(status, 120);
+        }
+    }
+}
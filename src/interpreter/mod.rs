@@ -1,40 +1,156 @@
+mod control;
+mod lowered;
+mod scheduler;
+mod snapshot;
+
+pub use self::{
+    control::{Control, Handler, Operator, State},
+    lowered::evaluate_lowered,
+    scheduler::Scheduler,
+    snapshot::SnapshotError,
+};
 use {
-    crate::ir::{Atom, Program},
-    std::{fmt::Debug, mem::swap},
+    crate::ir::{Atom, Number, Program},
+    core::fmt::{self, Display},
+    std::{collections::HashMap, fmt::Debug, mem::swap, rc::Rc},
 };
 
-#[derive(Clone, Debug)]
+/// Values compare structurally, closures by procedure and captured values.
+#[derive(Clone, PartialEq, Debug)]
 pub enum Value<B> {
     Builtin(B),
-    Number(u64),
+    Number(Number),
     String(String),
     Closure(u32, Vec<Value<B>>),
+    /// Immutable array, also used as a tuple.
+    Array(Rc<Vec<Value<B>>>),
+    /// Immutable hash map.
+    Map(Rc<HashMap<Key, Value<B>>>),
+    /// Returns from the body of the `handle` whose handler has this id.
+    Return(usize),
+    /// Resumes a `perform` with the id of its handler, the handler and the
+    /// continuation.
+    Resume(Rc<(usize, Value<B>, Value<B>)>),
+    /// Finishes the fiber with this id.
+    Finish(usize),
+    /// Handle of a fiber.
+    Fiber(usize),
+    /// Handle of a channel.
+    Channel(usize),
+    /// Ends evaluation, returning its arguments to the host. See
+    /// [`crate::engine`].
+    Halt,
+}
+
+/// Key of a [`Value::Map`].
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Key {
+    Number(Number),
+    String(String),
 }
 
-pub fn evaluate<B: Clone + Debug, R, F: FnMut(&Program<B>, &mut Vec<Value<B>>) -> Option<R>>(
+impl<B> Value<B> {
+    /// The value as a map key, if it is a number or a string.
+    #[must_use]
+    pub fn key(&self) -> Option<Key> {
+        match self {
+            Self::Number(n) => Some(Key::Number(n.clone())),
+            Self::String(s) => Some(Key::String(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl<B> From<Key> for Value<B> {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Number(n) => Self::Number(n),
+            Key::String(s) => Self::String(s),
+        }
+    }
+}
+
+/// Values print as literals where possible, e.g. `[1 “a”]` or `{“a”: 1}`.
+/// Map entries are ordered by key.
+impl<B: Display> Display for Value<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Builtin(builtin) => write!(f, "{builtin}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write!(f, "“{s}”"),
+            Self::Closure(id, _) => write!(f, "<closure {id}>"),
+            Self::Return(_) => write!(f, "<return>"),
+            Self::Resume(_) => write!(f, "<resume>"),
+            Self::Finish(_) => write!(f, "<finish>"),
+            Self::Fiber(id) => write!(f, "<fiber {id}>"),
+            Self::Channel(id) => write!(f, "<channel {id}>"),
+            Self::Halt => write!(f, "<halt>"),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Self::Map(map) => {
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{key}: {value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write!(f, "“{s}”"),
+        }
+    }
+}
+
+pub fn evaluate<
+    B: Clone + Debug + Control,
+    R,
+    F: FnMut(&Program<B>, &mut Vec<Value<B>>) -> Option<R>,
+>(
     program: &Program<B>,
     mut builtin: F,
     call: &[Value<B>],
 ) -> R {
-    let mut call = call.to_vec();
-    loop {
+    Scheduler::new(call.to_vec()).run(|state| {
         // eprint!("→ ");
-        // for value in &call {
+        // for value in &state.call {
         //     eprint!("{:?} ", value);
         // }
         // eprintln!();
-        if let Some(result) = iterate(program, &mut builtin, &mut call) {
-            return result;
-        }
-    }
+        iterate(program, &mut builtin, state)
+    })
 }
 
-pub fn iterate<B: Clone, R, F: FnOnce(&Program<B>, &mut Vec<Value<B>>) -> Option<R>>(
+pub fn iterate<B: Clone + Control, R, F: FnOnce(&Program<B>, &mut Vec<Value<B>>) -> Option<R>>(
     program: &Program<B>,
     builtin: F,
-    call: &mut Vec<Value<B>>,
+    state: &mut State<B>,
 ) -> Option<R> {
-    assert!(!call.is_empty());
+    assert!(!state.call.is_empty());
+
+    // Control operators and continuations
+    if state.control() {
+        return None;
+    }
+    let call = &mut state.call;
 
     // Builtins
     if let Value::Builtin(_) = &call[0] {
@@ -62,7 +178,7 @@ pub fn iterate<B: Clone, R, F: FnOnce(&Program<B>, &mut Vec<Value<B>>) -> Option
         .iter()
         .map(|atom| match atom {
             Atom::Builtin { builtin, .. } => Value::Builtin(builtin.clone()),
-            Atom::Number { value, .. } => Value::Number(*value),
+            Atom::Number { value, .. } => Value::Number(value.clone()),
             Atom::String { value, .. } => Value::String(value.clone()),
             Atom::Reference { id, .. } => {
                 // Lookup in closure, then arguments
@@ -0,0 +1,267 @@
+//! Cooperative green threads.
+//!
+//! Every fiber is a [`State`]. The scheduler runs the ready fibers round-robin,
+//! switching after a fixed number of steps or when a fiber yields, blocks or
+//! finishes, so a program is always scheduled the same way.
+//!
+//! `spawn f ret` starts a fiber calling `f done` and continues with
+//! `ret fiber`. The fiber finishes when any fiber calls `done x`, and
+//! `join fiber k` waits for that and continues with `k x`. The fiber calling
+//! `done` stops. `yield k` lets the other fibers
+//! run first. `channel ret` creates an unbounded channel, `send channel value
+//! k` never blocks and `recv channel k` waits for a value. Values are
+//! received in the order they were sent, by the fibers that have waited the
+//! longest.
+
+use {
+    super::{Control, Operator, State, Value},
+    std::{collections::VecDeque, mem::take},
+};
+
+/// Number of steps a fiber runs before the next fiber gets a turn.
+const QUANTUM: usize = 1024;
+
+/// Round-robin scheduler of fibers.
+#[derive(Clone, Debug)]
+pub struct Scheduler<B> {
+    pub(super) fibers:   Vec<Fiber<B>>,
+    pub(super) channels: Vec<Channel<B>>,
+    /// Fibers that can run, the current one first.
+    pub(super) ready:    VecDeque<usize>,
+    /// Steps left in the turn of the current fiber.
+    pub(super) slice:    usize,
+}
+
+/// A fiber that is blocked in `recv` or `join` has its continuation as the
+/// current call.
+#[derive(Clone, Debug)]
+pub(super) struct Fiber<B> {
+    pub(super) state:   State<B>,
+    /// The value passed to `done` once finished.
+    pub(super) result:  Option<Value<B>>,
+    /// Fibers waiting in `join` for this one.
+    pub(super) joiners: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Channel<B> {
+    pub(super) values:    VecDeque<Value<B>>,
+    /// Fibers waiting in `recv`, longest waiting first.
+    pub(super) receivers: VecDeque<usize>,
+}
+
+impl<B: Clone + Control> Scheduler<B> {
+    /// Scheduler with a single fiber evaluating `call`.
+    #[must_use]
+    pub fn new(call: Vec<Value<B>>) -> Self {
+        Self {
+            fibers:   vec![Fiber::new(call)],
+            channels: Vec::new(),
+            ready:    VecDeque::from([0]),
+            slice:    QUANTUM,
+        }
+    }
+
+    /// Run the fibers until `step` returns a result. The `step` function
+    /// evaluates the current call of a fiber.
+    ///
+    /// # Panics
+    ///
+    /// Panics when all fibers are blocked or on a malformed call.
+    pub fn run<R>(&mut self, mut step: impl FnMut(&mut State<B>) -> Option<R>) -> R {
+        loop {
+            if let Some(result) = self.run_for(QUANTUM, &mut step) {
+                return result;
+            }
+        }
+    }
+
+    /// Like [`Self::run`], but pause after `steps` steps and return `None`.
+    /// Pausing does not change how the fibers are scheduled.
+    ///
+    /// # Panics
+    ///
+    /// Panics when all fibers are blocked or on a malformed call.
+    pub fn run_for<R>(
+        &mut self,
+        steps: usize,
+        mut step: impl FnMut(&mut State<B>) -> Option<R>,
+    ) -> Option<R> {
+        for _ in 0..steps {
+            let Some(&current) = self.ready.front() else {
+                panic!("Deadlock: every fiber is blocked.")
+            };
+            if !self.schedule(current)
+                && let Some(result) = step(&mut self.fibers[current].state)
+            {
+                return Some(result);
+            }
+            // Round-robin when the fiber used up its turn.
+            self.slice -= 1;
+            if self.ready.front() != Some(&current) {
+                self.slice = QUANTUM;
+            } else if self.slice == 0 {
+                self.ready.rotate_left(1);
+                self.slice = QUANTUM;
+            }
+        }
+        None
+    }
+
+    /// Evaluate the current call of fiber `current` if it is a concurrency
+    /// operator. Returns `false` if it is something else.
+    fn schedule(&mut self, current: usize) -> bool {
+        let call = &self.fibers[current].state.call;
+        let operator = match &call[0] {
+            Value::Builtin(builtin) => builtin.control(),
+            Value::Finish(fiber) => {
+                let [_, result] = call.as_slice() else {
+                    panic!("`done` takes one argument.")
+                };
+                let (fiber, result) = (*fiber, result.clone());
+                // The current fiber has nothing left to do after `done`.
+                self.ready.pop_front();
+                if fiber != current {
+                    self.stop(fiber);
+                }
+                // A fiber finishes once, later calls of `done` only end the
+                // calling fiber.
+                if self.fibers[fiber].result.is_none() {
+                    for joiner in take(&mut self.fibers[fiber].joiners) {
+                        self.wake(joiner, result.clone());
+                    }
+                    self.fibers[fiber].result = Some(result);
+                }
+                return true;
+            }
+            _ => None,
+        };
+        let next = match (operator, call.as_slice()) {
+            (Some(Operator::Spawn), [_, f, ret]) => {
+                let fiber = self.fibers.len();
+                let (f, ret) = (f.clone(), ret.clone());
+                self.fibers.push(Fiber::new(vec![f, Value::Finish(fiber)]));
+                self.ready.push_back(fiber);
+                vec![ret, Value::Fiber(fiber)]
+            }
+            (Some(Operator::Yield), [_, k]) => {
+                let k = k.clone();
+                self.ready.rotate_left(1);
+                vec![k]
+            }
+            (Some(Operator::Channel), [_, ret]) => {
+                let channel = self.channels.len();
+                let ret = ret.clone();
+                self.channels.push(Channel {
+                    values:    VecDeque::new(),
+                    receivers: VecDeque::new(),
+                });
+                vec![ret, Value::Channel(channel)]
+            }
+            (Some(Operator::Send), [_, Value::Channel(channel), value, k]) => {
+                let (channel, value, k) = (*channel, value.clone(), k.clone());
+                let channel = &mut self.channels[channel];
+                if let Some(receiver) = channel.receivers.pop_front() {
+                    self.wake(receiver, value);
+                } else {
+                    channel.values.push_back(value);
+                }
+                vec![k]
+            }
+            (Some(Operator::Receive), [_, Value::Channel(channel), k]) => {
+                let (channel, k) = (*channel, k.clone());
+                let channel = &mut self.channels[channel];
+                if let Some(value) = channel.values.pop_front() {
+                    vec![k, value]
+                } else {
+                    channel.receivers.push_back(current);
+                    self.block(current, k);
+                    return true;
+                }
+            }
+            (Some(Operator::Join), [_, Value::Fiber(fiber), k]) => {
+                let (fiber, k) = (*fiber, k.clone());
+                if let Some(result) = &self.fibers[fiber].result {
+                    vec![k, result.clone()]
+                } else {
+                    self.fibers[fiber].joiners.push(current);
+                    self.block(current, k);
+                    return true;
+                }
+            }
+            (Some(Operator::Handle | Operator::Perform) | None, _) => return false,
+            (Some(operator), _) => panic!("Invalid arguments to {operator:?}."),
+        };
+        self.fibers[current].state.call = next;
+        true
+    }
+
+    /// Block the current fiber with continuation `k`.
+    fn block(&mut self, current: usize, k: Value<B>) {
+        self.ready.pop_front();
+        self.fibers[current].state.call = vec![k];
+    }
+
+    /// Stop `fiber`, which may be ready or blocked, from running again.
+    fn stop(&mut self, fiber: usize) {
+        self.ready.retain(|&f| f != fiber);
+        for channel in &mut self.channels {
+            channel.receivers.retain(|&f| f != fiber);
+        }
+        for other in &mut self.fibers {
+            other.joiners.retain(|&f| f != fiber);
+        }
+    }
+
+    /// Continue a blocked fiber with `value`.
+    fn wake(&mut self, fiber: usize, value: Value<B>) {
+        self.fibers[fiber].state.call.push(value);
+        self.ready.push_back(fiber);
+    }
+}
+
+impl<B> Fiber<B> {
+    const fn new(call: Vec<Value<B>>) -> Self {
+        Self {
+            state:   State::new(call),
+            result:  None,
+            joiners: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::status;
+
+    #[test]
+    fn join() {
+        assert_eq!(
+            status("main exit: spawn (done: done 7) (f: join f exit)\n"),
+            7
+        );
+    }
+
+    #[test]
+    fn channels() {
+        let source = "main exit: channel (ch: spawn (done: send ch 1 (: send ch 2 (: done 0))) \
+                      (f: recv ch (a: recv ch (b: exit (sub b a)))))\n";
+        assert_eq!(status(source), 1);
+    }
+
+    #[test]
+    fn yield_round_robin() {
+        let source = "main exit: channel (ch: spawn (done: send ch 1 (: done 0)) (f: yield (: \
+                      send ch 2 (: recv ch (a: exit a)))))\n";
+        assert_eq!(status(source), 1);
+    }
+
+    /// The main fiber finishes the first fiber with its `done`, which a third
+    /// fiber joins.
+    #[test]
+    fn finish_other_fiber() {
+        let source = "main exit: channel (ch: spawn (done: send ch done (: recv ch (x: exit 99))) \
+                      (f: spawn (d: join f exit) (g: recv ch (done: done 42))))\n";
+        assert_eq!(status(source), 42);
+    }
+}
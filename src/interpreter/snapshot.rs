@@ -0,0 +1,549 @@
+//! Snapshots of a running program.
+//!
+//! A [`Scheduler`] holds the complete state of a running program, so it can be
+//! saved to bytes and resumed later or in another process. The encoding is
+//! versioned and independent of the platform. Closures are stored as their
+//! procedure id and captured values and builtins by their [`Display`] text,
+//! which [`crate::builtins::Builtin::decode`] reads back. A snapshot can only
+//! be resumed with the same program, including optimisations, which is
+//! checked with [`Program::fingerprint`].
+//!
+//! Values shared between fibers or containers are stored once per use, so
+//! sharing is not preserved. Snapshots are trusted: one that decodes but was
+//! tampered with can make evaluation panic.
+
+use {
+    super::{
+        Handler, Key, Scheduler, State, Value,
+        scheduler::{Channel, Fiber},
+    },
+    crate::ir::{Atom, Number, Program},
+    core::fmt::{self, Display},
+    num_bigint::BigInt,
+    std::{collections::VecDeque, error::Error, rc::Rc},
+};
+
+const MAGIC: &[u8; 4] = b"OLUS";
+const VERSION: u8 = 1;
+
+/// Reasons a snapshot can not be resumed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SnapshotError {
+    /// The bytes are not a snapshot or are truncated.
+    Malformed { offset: usize },
+    /// The snapshot was made by an incompatible version.
+    Version { found: u8 },
+    /// The snapshot was made with a different program.
+    Program { expected: u64, found: u64 },
+    /// The snapshot uses a builtin that is not available.
+    UnknownBuiltin { name: String },
+}
+
+impl<B: Display> Program<B> {
+    /// Stable hash identifying the program, for use in snapshots.
+    #[must_use]
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv::default();
+        for procedure in &self.procedures {
+            hash.write_usize(procedure.arguments.len());
+            for argument in &procedure.arguments {
+                hash.write_u32(argument.id);
+            }
+            hash.write_usize(procedure.closure.len());
+            for id in &procedure.closure {
+                hash.write_u32(*id);
+            }
+            hash.write_usize(procedure.body.len());
+            for atom in &procedure.body {
+                match atom {
+                    Atom::Builtin { builtin, .. } => hash.write_str(0, &builtin.to_string()),
+                    Atom::Reference { id, .. } => {
+                        hash.write(&[1]);
+                        hash.write_u32(*id);
+                    }
+                    Atom::String { value, .. } => hash.write_str(2, value),
+                    Atom::Number { value, .. } => hash.write_str(3, &value.to_string()),
+                }
+            }
+        }
+        hash.0
+    }
+}
+
+impl<B: Clone + Display> Scheduler<B> {
+    /// Save the state of the program to bytes.
+    #[must_use]
+    pub fn snapshot(&self, program: &Program<B>) -> Vec<u8> {
+        let mut encoder = Encoder(MAGIC.to_vec());
+        encoder.0.push(VERSION);
+        encoder.0.extend(program.fingerprint().to_le_bytes());
+        encoder.length(self.fibers.len());
+        for fiber in &self.fibers {
+            encoder.state(&fiber.state);
+            encoder.option(fiber.result.as_ref());
+            encoder.indices(&fiber.joiners);
+        }
+        encoder.length(self.channels.len());
+        for channel in &self.channels {
+            encoder.length(channel.values.len());
+            for value in &channel.values {
+                encoder.value(value);
+            }
+            encoder.indices(channel.receivers.iter());
+        }
+        encoder.indices(self.ready.iter());
+        encoder.length(self.slice);
+        encoder.0
+    }
+
+    /// Restore the state of a program from a snapshot, resolving builtins by
+    /// name with `builtins`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a snapshot of `program`.
+    pub fn resume(
+        program: &Program<B>,
+        builtins: impl Fn(&str) -> Option<B>,
+        bytes: &[u8],
+    ) -> Result<Self, SnapshotError> {
+        let mut decoder = Decoder {
+            bytes,
+            offset: 0,
+            builtins: &builtins,
+        };
+        if decoder.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::Malformed { offset: 0 });
+        }
+        let version = decoder.byte()?;
+        if version != VERSION {
+            return Err(SnapshotError::Version { found: version });
+        }
+        let mut found = [0; 8];
+        found.copy_from_slice(decoder.take(8)?);
+        let found = u64::from_le_bytes(found);
+        let expected = program.fingerprint();
+        if found != expected {
+            return Err(SnapshotError::Program { expected, found });
+        }
+        let fibers = decoder.list(|decoder| {
+            Ok(Fiber {
+                state:   decoder.state()?,
+                result:  decoder.option()?,
+                joiners: decoder.list(Decoder::length)?,
+            })
+        })?;
+        let channels = decoder.list(|decoder| {
+            Ok(Channel {
+                values:    decoder.list(Decoder::value)?.into(),
+                receivers: decoder.list(Decoder::length)?.into(),
+            })
+        })?;
+        let ready: VecDeque<_> = decoder.list(Decoder::length)?.into();
+        let slice = decoder.length()?;
+
+        // Check handles so resuming can not index out of bounds.
+        let in_bounds = fibers
+            .iter()
+            .flat_map(|fiber| &fiber.joiners)
+            .chain(channels.iter().flat_map(|channel| &channel.receivers))
+            .chain(&ready)
+            .all(|fiber| *fiber < fibers.len());
+        if !in_bounds || slice == 0 || decoder.offset != bytes.len() {
+            return Err(SnapshotError::Malformed {
+                offset: decoder.offset,
+            });
+        }
+        Ok(Self {
+            fibers,
+            channels,
+            ready,
+            slice,
+        })
+    }
+}
+
+/// FNV-1a, which unlike the standard library hasher is stable.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    fn write_str(&mut self, tag: u8, value: &str) {
+        self.write(&[tag]);
+        self.write_usize(value.len());
+        self.write(value.as_bytes());
+    }
+}
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    /// Unsigned LEB128.
+    #[allow(clippy::cast_possible_truncation)]
+    fn length(&mut self, mut value: usize) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.length(bytes.len());
+        self.0.extend(bytes);
+    }
+
+    fn indices<'a>(&mut self, indices: impl IntoIterator<Item = &'a usize>) {
+        let indices = indices.into_iter().collect::<Vec<_>>();
+        self.length(indices.len());
+        for index in indices {
+            self.length(*index);
+        }
+    }
+
+    fn state<B: Display>(&mut self, state: &State<B>) {
+        self.values(&state.call);
+        self.length(state.next_handler);
+        self.length(state.handlers.len());
+        for handler in &state.handlers {
+            self.length(handler.id);
+            self.value(&handler.handler);
+            self.value(&handler.ret);
+        }
+    }
+
+    fn option<B: Display>(&mut self, value: Option<&Value<B>>) {
+        match value {
+            None => self.0.push(0),
+            Some(value) => {
+                self.0.push(1);
+                self.value(value);
+            }
+        }
+    }
+
+    fn values<B: Display>(&mut self, values: &[Value<B>]) {
+        self.length(values.len());
+        for value in values {
+            self.value(value);
+        }
+    }
+
+    fn number(&mut self, number: &Number) {
+        match number {
+            Number::Integer(n) => {
+                self.0.push(0);
+                self.bytes(&n.to_signed_bytes_le());
+            }
+            Number::Float(n) => {
+                self.0.push(1);
+                self.0.extend(n.to_bits().to_le_bytes());
+            }
+        }
+    }
+
+    fn value<B: Display>(&mut self, value: &Value<B>) {
+        match value {
+            Value::Builtin(builtin) => {
+                self.0.push(0);
+                self.bytes(builtin.to_string().as_bytes());
+            }
+            Value::Number(n) => {
+                self.0.push(1);
+                self.number(n);
+            }
+            Value::String(s) => {
+                self.0.push(2);
+                self.bytes(s.as_bytes());
+            }
+            Value::Closure(id, captures) => {
+                self.0.push(3);
+                self.length(*id as usize);
+                self.values(captures);
+            }
+            Value::Array(values) => {
+                self.0.push(4);
+                self.values(values);
+            }
+            Value::Map(map) => {
+                // Sorted, so equal maps have equal snapshots.
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                self.0.push(5);
+                self.length(entries.len());
+                for (key, value) in entries {
+                    match key {
+                        Key::Number(n) => {
+                            self.0.push(0);
+                            self.number(n);
+                        }
+                        Key::String(s) => {
+                            self.0.push(1);
+                            self.bytes(s.as_bytes());
+                        }
+                    }
+                    self.value(value);
+                }
+            }
+            Value::Return(id) => {
+                self.0.push(6);
+                self.length(*id);
+            }
+            Value::Resume(resumption) => {
+                self.0.push(7);
+                self.length(resumption.0);
+                self.value(&resumption.1);
+                self.value(&resumption.2);
+            }
+            Value::Finish(id) => {
+                self.0.push(8);
+                self.length(*id);
+            }
+            Value::Fiber(id) => {
+                self.0.push(9);
+                self.length(*id);
+            }
+            Value::Channel(id) => {
+                self.0.push(10);
+                self.length(*id);
+            }
+            Value::Halt => self.0.push(11),
+        }
+    }
+}
+
+struct Decoder<'a, B> {
+    bytes:    &'a [u8],
+    offset:   usize,
+    builtins: &'a dyn Fn(&str) -> Option<B>,
+}
+
+impl<'a, B> Decoder<'a, B> {
+    const fn malformed(&self) -> SnapshotError {
+        SnapshotError::Malformed {
+            offset: self.offset,
+        }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SnapshotError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(length))
+            .ok_or_else(|| self.malformed())?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<usize, SnapshotError> {
+        let mut value = 0_usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.byte()?;
+            value |= usize::from(byte & 0x7f)
+                .checked_shl(shift)
+                .ok_or_else(|| self.malformed())?;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(self.malformed())
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let length = self.length()?;
+        self.take(length)
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.malformed())
+    }
+
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, SnapshotError>,
+    ) -> Result<Vec<T>, SnapshotError> {
+        let length = self.length()?;
+        // Every item takes at least a byte, which bounds the allocation.
+        if length > self.bytes.len() - self.offset {
+            return Err(self.malformed());
+        }
+        (0..length).map(|_| item(self)).collect()
+    }
+
+    fn state(&mut self) -> Result<State<B>, SnapshotError> {
+        Ok(State {
+            call:         self.list(Self::value)?,
+            next_handler: self.length()?,
+            handlers:     self.list(|decoder| {
+                Ok(Handler {
+                    id:      decoder.length()?,
+                    handler: decoder.value()?,
+                    ret:     decoder.value()?,
+                })
+            })?,
+        })
+    }
+
+    fn option(&mut self) -> Result<Option<Value<B>>, SnapshotError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => self.value().map(Some),
+            _ => Err(self.malformed()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Number, SnapshotError> {
+        match self.byte()? {
+            0 => Ok(Number::Integer(BigInt::from_signed_bytes_le(self.bytes()?))),
+            1 => {
+                let bits = self.take(8)?.try_into().unwrap();
+                Ok(Number::Float(f64::from_bits(u64::from_le_bytes(bits))))
+            }
+            _ => Err(self.malformed()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value<B>, SnapshotError> {
+        Ok(match self.byte()? {
+            0 => {
+                let name = self.string()?;
+                let builtin = (self.builtins)(&name);
+                Value::Builtin(builtin.ok_or(SnapshotError::UnknownBuiltin { name })?)
+            }
+            1 => Value::Number(self.number()?),
+            2 => Value::String(self.string()?),
+            3 => {
+                let id = u32::try_from(self.length()?).map_err(|_| self.malformed())?;
+                Value::Closure(id, self.list(Self::value)?)
+            }
+            4 => Value::Array(Rc::new(self.list(Self::value)?)),
+            5 => {
+                let entries = self.list(|decoder| {
+                    let key = match decoder.byte()? {
+                        0 => Key::Number(decoder.number()?),
+                        1 => Key::String(decoder.string()?),
+                        _ => return Err(decoder.malformed()),
+                    };
+                    Ok((key, decoder.value()?))
+                })?;
+                Value::Map(Rc::new(entries.into_iter().collect()))
+            }
+            6 => Value::Return(self.length()?),
+            7 => Value::Resume(Rc::new((self.length()?, self.value()?, self.value()?))),
+            8 => Value::Finish(self.length()?),
+            9 => Value::Fiber(self.length()?),
+            10 => Value::Channel(self.length()?),
+            11 => Value::Halt,
+            _ => return Err(self.malformed()),
+        })
+    }
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed { offset } => write!(f, "malformed snapshot at byte {offset}"),
+            Self::Version { found } => {
+                write!(f, "snapshot version {found} is not supported")
+            }
+            Self::Program { expected, found } => write!(
+                f,
+                "snapshot is of program {found:016x}, not {expected:016x}"
+            ),
+            Self::UnknownBuiltin { name } => write!(f, "snapshot uses unknown builtin `{name}`"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{builtins::Builtin, tests::program},
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn round_trip() {
+        let program = program("main exit: exit 0\n");
+        let map = HashMap::from([
+            (Key::String("a".to_string()), Value::Return(3)),
+            (Key::Number(Number::Float(0.5)), Value::Finish(0)),
+        ]);
+        let mut scheduler = Scheduler::new(vec![
+            Value::Builtin(Builtin::Host { index: 2, arity: 3 }),
+            Value::Builtin(Builtin::Unreachable),
+            Value::Builtin(Builtin::Add),
+            Value::Number(Number::Integer(BigInt::from(-300))),
+            Value::Number(Number::Float(1.5)),
+            Value::String("“x”".to_string()),
+            Value::Closure(0, vec![Value::Halt]),
+            Value::Array(Rc::new(vec![Value::Fiber(0), Value::Channel(1)])),
+            Value::Map(Rc::new(map)),
+            Value::Resume(Rc::new((1, Value::Halt, Value::Return(1)))),
+        ]);
+        let state = &mut scheduler.fibers[0].state;
+        state.next_handler = 2;
+        state.handlers.push(Handler {
+            id:      1,
+            handler: Value::Builtin(Builtin::Print),
+            ret:     Value::Halt,
+        });
+
+        let bytes = scheduler.snapshot(&program);
+        let resumed = Scheduler::resume(&program, Builtin::decode, &bytes).unwrap();
+        assert_eq!(resumed.fibers[0].state.call, scheduler.fibers[0].state.call);
+        assert_eq!(resumed.fibers[0].state.next_handler, 2);
+        assert_eq!(resumed.snapshot(&program), bytes);
+    }
+
+    #[test]
+    fn errors() {
+        let program = program("main exit: exit 0\n");
+        let host = Value::Builtin(Builtin::Host { index: 0, arity: 1 });
+        let bytes = Scheduler::new(vec![host]).snapshot(&program);
+        // Host functions can not be resolved by name.
+        assert_eq!(
+            Scheduler::resume(&program, Builtin::resolve, &bytes).err(),
+            Some(SnapshotError::UnknownBuiltin {
+                name: "host 0/1".to_string(),
+            })
+        );
+        let resume = |program, bytes| Scheduler::resume(program, Builtin::decode, bytes).err();
+        assert_eq!(resume(&program, &bytes), None);
+        assert!(matches!(
+            resume(&program, &bytes[..bytes.len() - 1]),
+            Some(SnapshotError::Malformed { .. })
+        ));
+        let other = crate::tests::program("main exit: exit 1\n");
+        assert!(matches!(
+            resume(&other, &bytes),
+            Some(SnapshotError::Program { .. })
+        ));
+    }
+}
@@ -0,0 +1,531 @@
+//! k-CFA control-flow analysis.
+//!
+//! An abstract interpretation of the program where every binding is tagged
+//! with the last `k` call sites. Since every procedure body is a single call,
+//! call sites are identified by the id of the procedure containing them.
+//! See <https://matt.might.net/articles/implementation-of-kcfa-and-0cfa/>.
+
+use {
+    super::{Atom, Program},
+    crate::{Diagnostic, FileId, front::Span},
+    std::collections::{HashMap, HashSet},
+};
+
+/// Control-flow behaviour of builtins.
+pub trait Flow {
+    /// Number of arguments the builtin takes.
+    fn arity(&self) -> usize;
+
+    /// The calls the builtin can make, as the index of the called argument and
+    /// the values passed to it.
+    fn flow(&self) -> Vec<(usize, Vec<Flowing>)>;
+
+    /// Arguments the builtin stores where they can later be retrieved and
+    /// called, such as elements of a container. These escape to the outside
+    /// world.
+    fn escaping(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// A builtin without arguments that traps when called, for the bodies of
+    /// procedures that are never called.
+    fn unreachable() -> Self;
+}
+
+/// A value a builtin passes to a continuation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Flowing {
+    /// One of the arguments of the builtin.
+    Argument(usize),
+    /// A value that is not a closure, such as a number.
+    Opaque,
+    /// Any value, such as an element of a container.
+    Unknown,
+}
+
+/// Something a call site may call.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Callee<B> {
+    Procedure(u32),
+    Builtin(B),
+    /// A value from outside the program.
+    Unknown,
+}
+
+/// A call whose argument count does not match the callee.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ArityMismatch<B> {
+    pub site:     u32,
+    pub source:   Span,
+    pub callee:   Callee<B>,
+    pub expected: usize,
+    pub found:    usize,
+}
+
+/// Result of k-CFA on a program.
+pub struct ControlFlow<B> {
+    targets:    HashMap<u32, Vec<Callee<B>>>,
+    reached:    HashSet<u32>,
+    created:    HashSet<u32>,
+    mismatches: Vec<ArityMismatch<B>>,
+}
+
+/// The last `k` call sites.
+type Context = Vec<u32>;
+
+/// Abstract runtime values.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum Value {
+    /// A procedure with the contexts of its captured variables.
+    Closure(u32, Vec<Context>),
+    /// The builtin at a position in the body of a procedure.
+    Builtin(u32, usize),
+    Opaque,
+    Unknown,
+}
+
+/// A procedure body to evaluate, with the contexts of its closure and
+/// arguments.
+type State = (u32, Vec<Context>, Context);
+
+struct Analysis<'a, B> {
+    program:  &'a Program<B>,
+    k:        usize,
+    store:    HashMap<(u32, Context), HashSet<Value>>,
+    readers:  HashMap<(u32, Context), HashSet<State>>,
+    seen:     HashSet<State>,
+    worklist: Vec<State>,
+    result:   ControlFlow<B>,
+}
+
+impl<B: Flow + Clone + PartialEq> ControlFlow<B> {
+    /// Analyse the program as called from outside through `root`.
+    #[must_use]
+    pub fn analyse(program: &Program<B>, root: u32, k: usize) -> Self {
+        let mut analysis = Analysis {
+            program,
+            k,
+            store: HashMap::new(),
+            readers: HashMap::new(),
+            seen: HashSet::new(),
+            worklist: Vec::new(),
+            result: Self {
+                targets:    HashMap::new(),
+                reached:    HashSet::new(),
+                created:    HashSet::new(),
+                mismatches: Vec::new(),
+            },
+        };
+        if let Some(proc) = program.procedure_by_id(root) {
+            let unknown = HashSet::from([Value::Unknown]);
+            for parameter in &proc.arguments[1..] {
+                analysis.join((parameter.id, Context::new()), &unknown);
+            }
+            let closure = vec![Context::new(); proc.closure.len()];
+            analysis.enqueue((root, closure, Context::new()));
+        }
+        while let Some(state) = analysis.worklist.pop() {
+            analysis.step(&state);
+        }
+        analysis.result
+    }
+}
+
+impl<B> ControlFlow<B> {
+    /// Everything the call in the body of procedure `site` may call.
+    #[must_use]
+    pub fn targets(&self, site: u32) -> &[Callee<B>] {
+        self.targets.get(&site).map_or(&[], Vec::as_slice)
+    }
+
+    /// The procedure called by `site`, if it can only call one procedure.
+    #[must_use]
+    pub fn unique_target(&self, site: u32) -> Option<u32> {
+        match self.targets(site) {
+            [Callee::Procedure(id)] => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Whether the body of a procedure may be evaluated.
+    #[must_use]
+    pub fn is_reached(&self, procedure: u32) -> bool {
+        self.reached.contains(&procedure)
+    }
+
+    /// Whether a closure of a procedure may be created.
+    #[must_use]
+    pub fn is_created(&self, procedure: u32) -> bool {
+        self.created.contains(&procedure)
+    }
+
+    /// Calls with the wrong number of arguments, including calls through
+    /// higher-order flows.
+    #[must_use]
+    pub fn arity_mismatches(&self) -> &[ArityMismatch<B>] {
+        &self.mismatches
+    }
+}
+
+impl<B: Flow + Clone + PartialEq> Analysis<'_, B> {
+    fn step(&mut self, state: &State) {
+        let (id, _, context) = state;
+        let program = self.program;
+        let proc = program
+            .procedure_by_id(*id)
+            .expect("States refer to procedures.");
+        self.result.reached.insert(*id);
+
+        let values = proc
+            .body
+            .iter()
+            .enumerate()
+            .map(|(position, atom)| self.eval(state, position, atom))
+            .collect::<Vec<_>>();
+        let mut time = vec![*id];
+        time.extend(context.iter().copied());
+        time.truncate(self.k);
+
+        let (head, arguments) = values.split_first().expect("Bodies are not empty.");
+        for callee in head.clone() {
+            self.apply(*id, &callee, arguments, &time, 0);
+        }
+    }
+
+    /// Abstract value of an atom in the body of the procedure of `state`.
+    fn eval(&mut self, state: &State, position: usize, atom: &Atom<B>) -> HashSet<Value> {
+        let (id, closure, context) = state;
+        let program = self.program;
+        let proc = program.procedure_by_id(*id).unwrap();
+        let lookup = |var: u32| -> Option<Context> {
+            if let Some(i) = proc.closure.iter().position(|v| *v == var) {
+                return Some(closure[i].clone());
+            }
+            proc.arguments[1..]
+                .iter()
+                .any(|arg| arg.id == var)
+                .then(|| context.clone())
+        };
+        match atom {
+            Atom::Builtin { .. } => HashSet::from([Value::Builtin(*id, position)]),
+            Atom::Number { .. } | Atom::String { .. } => HashSet::from([Value::Opaque]),
+            Atom::Reference { id: var, .. } if var == id => {
+                HashSet::from([Value::Closure(*id, closure.clone())])
+            }
+            Atom::Reference { id: var, .. } => {
+                if let Some(context) = lookup(*var) {
+                    let address = (*var, context);
+                    self.readers
+                        .entry(address.clone())
+                        .or_default()
+                        .insert(state.clone());
+                    return self.store.get(&address).cloned().unwrap_or_default();
+                }
+                if let Some(created) = program.procedure_by_id(*var) {
+                    self.result.created.insert(*var);
+                    let captured = created
+                        .closure
+                        .iter()
+                        .map(|v| lookup(*v).unwrap_or_default())
+                        .collect();
+                    return HashSet::from([Value::Closure(*var, captured)]);
+                }
+                HashSet::from([Value::Unknown])
+            }
+        }
+    }
+
+    /// Call `callee` from `site` with the given argument values.
+    fn apply(
+        &mut self,
+        site: u32,
+        callee: &Value,
+        arguments: &[HashSet<Value>],
+        time: &Context,
+        depth: usize,
+    ) {
+        // Builtins calling builtins calling builtins is not going anywhere, so
+        // past this depth a builtin is treated like the outside world.
+        const MAX_DEPTH: usize = 8;
+        let program = self.program;
+        match callee {
+            Value::Closure(id, closure) => {
+                self.target(site, Callee::Procedure(*id), depth);
+                let proc = program.procedure_by_id(*id).unwrap();
+                if proc.arguments.len() != arguments.len() + 1 {
+                    self.mismatch(
+                        site,
+                        Callee::Procedure(*id),
+                        proc.arguments.len() - 1,
+                        arguments.len(),
+                    );
+                }
+                // The interpreter enters the procedure regardless, so do the same.
+                for (parameter, values) in proc.arguments[1..].iter().zip(arguments) {
+                    self.join((parameter.id, time.clone()), values);
+                }
+                self.enqueue((*id, closure.clone(), time.clone()));
+            }
+            Value::Builtin(procedure, position) => {
+                let Some(Atom::Builtin { builtin, .. }) = program
+                    .procedure_by_id(*procedure)
+                    .and_then(|p| p.body.get(*position))
+                else {
+                    unreachable!("Builtin values refer to builtin atoms.");
+                };
+                self.target(site, Callee::Builtin(builtin.clone()), depth);
+                if builtin.arity() != arguments.len() {
+                    self.mismatch(
+                        site,
+                        Callee::Builtin(builtin.clone()),
+                        builtin.arity(),
+                        arguments.len(),
+                    );
+                    return;
+                }
+                if depth > MAX_DEPTH {
+                    // Widen: any closure passed to the builtin may be called
+                    // with any values.
+                    for values in arguments {
+                        self.escape(site, values, time, depth);
+                    }
+                    return;
+                }
+                for escaping in builtin.escaping() {
+                    self.escape(site, &arguments[escaping], time, depth);
+                }
+                for (called, passed) in builtin.flow() {
+                    let passed = passed
+                        .iter()
+                        .map(|flowing| match flowing {
+                            Flowing::Argument(i) => arguments[*i].clone(),
+                            Flowing::Opaque => HashSet::from([Value::Opaque]),
+                            Flowing::Unknown => HashSet::from([Value::Unknown]),
+                        })
+                        .collect::<Vec<_>>();
+                    for callee in &arguments[called] {
+                        self.apply(site, callee, &passed, time, depth + 1);
+                    }
+                }
+            }
+            Value::Unknown => {
+                self.target(site, Callee::Unknown, depth);
+                for values in arguments {
+                    self.escape(site, values, time, depth);
+                }
+            }
+            // Calling a number is a type error, not a control flow.
+            Value::Opaque => {}
+        }
+    }
+
+    /// Anything passed to the outside world may be called with unknown values.
+    fn escape(&mut self, site: u32, values: &HashSet<Value>, time: &Context, depth: usize) {
+        for value in values {
+            if let Value::Closure(id, _) = value {
+                let arity = self.program.procedure_by_id(*id).unwrap().arguments.len() - 1;
+                let unknown = vec![HashSet::from([Value::Unknown]); arity];
+                self.apply(site, value, &unknown, time, depth + 1);
+            }
+        }
+    }
+
+    fn join(&mut self, address: (u32, Context), values: &HashSet<Value>) {
+        let entry = self.store.entry(address.clone()).or_default();
+        let before = entry.len();
+        entry.extend(values.iter().cloned());
+        if entry.len() != before {
+            for state in self.readers.get(&address).into_iter().flatten() {
+                self.worklist.push(state.clone());
+            }
+        }
+    }
+
+    fn enqueue(&mut self, state: State) {
+        if self.seen.insert(state.clone()) {
+            self.worklist.push(state);
+        }
+    }
+
+    fn target(&mut self, site: u32, callee: Callee<B>, depth: usize) {
+        // Calls made by builtins are not calls of the site itself.
+        if depth > 0 {
+            return;
+        }
+        let targets = self.result.targets.entry(site).or_default();
+        if !targets.contains(&callee) {
+            targets.push(callee);
+        }
+    }
+
+    fn mismatch(&mut self, site: u32, callee: Callee<B>, expected: usize, found: usize) {
+        let source = self
+            .program
+            .procedure_by_id(site)
+            .map_or_else(|| Span::splat(0), |p| p.body[0].source());
+        let mismatch = ArityMismatch {
+            site,
+            source,
+            callee,
+            expected,
+            found,
+        };
+        if !self.result.mismatches.contains(&mismatch) {
+            self.result.mismatches.push(mismatch);
+        }
+    }
+}
+
+impl<B> ArityMismatch<B> {
+    #[must_use]
+    pub fn diagnostic(&self, file: FileId) -> Diagnostic {
+        Diagnostic {
+            message: format!(
+                "Call passes {} arguments to a procedure that takes {}.",
+                self.found, self.expected
+            ),
+            span:    file.span(self.source.start..self.source.end),
+        }
+    }
+}
+
+impl<B: Flow + Clone + PartialEq> Program<B> {
+    /// Simplify the program using the results of k-CFA. Returns the number of
+    /// calls and procedures changed.
+    ///
+    /// Calls through a variable that can only reach a single procedure without
+    /// closure become direct calls. Procedures that are created but never
+    /// called get a body that traps, see [`Flow::unreachable`], so that what
+    /// they reference can be removed by [`Program::tree_shake`].
+    pub fn devirtualize(&mut self, root: u32, k: usize) -> usize {
+        let flow = ControlFlow::analyse(self, root, k);
+        let mut changes = 0;
+        for i in 0..self.procedures.len() {
+            let proc = &self.procedures[i];
+            let id = proc.id();
+            if !flow.is_reached(id) {
+                if flow.is_created(id) && !proc.is_trap() {
+                    let proc = &mut self.procedures[i];
+                    proc.body = vec![Atom::Builtin {
+                        source:  proc.source,
+                        builtin: B::unreachable(),
+                    }];
+                    changes += 1;
+                }
+                continue;
+            }
+            let Some(target) = flow.unique_target(id) else {
+                continue;
+            };
+            let direct = matches!(proc.body[0], Atom::Reference { id, .. } if id == target);
+            let closed = self
+                .procedure_by_id(target)
+                .is_some_and(|p| p.closure.is_empty());
+            if !direct && closed {
+                let source = self.procedures[i].body[0].source();
+                self.procedures[i].body[0] = Atom::Reference { source, id: target };
+                changes += 1;
+            }
+        }
+        changes
+    }
+}
+
+impl<B: Flow + PartialEq> super::Procedure<B> {
+    /// Whether the body is the trap put in by [`Program::devirtualize`].
+    fn is_trap(&self) -> bool {
+        matches!(self.body.as_slice(), [Atom::Builtin { builtin, .. }] if *builtin == B::unreachable())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            builtins::Builtin,
+            front::{compile, parse},
+            tests::program,
+        },
+    };
+
+    /// Builtins `shiftN` that call their first argument with the others.
+    #[derive(Clone, PartialEq, Debug)]
+    enum Shift {
+        Shift(usize),
+        Trap,
+    }
+
+    impl Flow for Shift {
+        fn arity(&self) -> usize {
+            match self {
+                Self::Shift(n) => *n,
+                Self::Trap => 0,
+            }
+        }
+
+        fn flow(&self) -> Vec<(usize, Vec<Flowing>)> {
+            match self {
+                Self::Shift(n) => vec![(0, (1..*n).map(Flowing::Argument).collect())],
+                Self::Trap => vec![],
+            }
+        }
+
+        fn unreachable() -> Self {
+            Self::Trap
+        }
+    }
+
+    fn main(program: &Program<impl Sized>) -> u32 {
+        program.procedure_by_name("main").unwrap().id()
+    }
+
+    #[test]
+    fn builtin_chains() {
+        // `done` is called by `shift2`, ten builtin calls deep.
+        let shifts = (2..=12).rev().map(|n| format!("shift{n}"));
+        let shifts = shifts.collect::<Vec<_>>().join(" ");
+        let source = format!("main exit: {shifts} done 0\ndone v: done v\n");
+        let root = parse(&source);
+        let shift = |name: &str| name.strip_prefix("shift")?.parse().ok().map(Shift::Shift);
+        let mut program = compile(source, &root, shift).unwrap();
+        program.closure_analysis();
+        let flow = ControlFlow::analyse(&program, main(&program), 0);
+        assert!(flow.arity_mismatches().is_empty());
+        assert!(flow.is_reached(program.procedure_by_name("done").unwrap().id()));
+    }
+
+    #[test]
+    fn direct_calls() {
+        let mut program = program("main exit: f g exit\nf k ret: k 1 ret\ng x ret: ret x\n");
+        let g = program.procedure_by_name("g").unwrap().id();
+        assert_eq!(program.devirtualize(main(&program), 0), 1);
+        let f = program.procedure_by_name("f").unwrap();
+        assert!(matches!(f.body[0], Atom::Reference { id, .. } if id == g));
+    }
+
+    #[test]
+    fn never_called() {
+        let mut program = program("main exit: f g exit\nf k ret: ret 0\ng x ret: ret x\n");
+        assert_eq!(program.devirtualize(main(&program), 0), 1);
+        let g = program.procedure_by_name("g").unwrap();
+        assert!(matches!(g.body.as_slice(), [Atom::Builtin {
+            builtin: Builtin::Unreachable,
+            ..
+        }]));
+        // Running it again changes nothing.
+        assert_eq!(program.devirtualize(main(&program), 0), 0);
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        let program = program("main exit: f exit\nf ret: add 1 2 3 ret\n");
+        let flow = ControlFlow::analyse(&program, main(&program), 0);
+        assert!(matches!(flow.arity_mismatches(), [ArityMismatch {
+            callee: Callee::Builtin(Builtin::Add),
+            expected: 3,
+            found: 4,
+            ..
+        }]));
+    }
+}
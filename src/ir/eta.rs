@@ -0,0 +1,131 @@
+//! Eta-reduction of forwarding procedures.
+
+use super::{Atom, Procedure, Program};
+
+impl<B> Procedure<B> {
+    /// If the body calls something other than an argument with a permutation
+    /// of the remaining arguments, returns for each position of the call the
+    /// index of the argument passed there.
+    fn forwarding(&self) -> Option<Vec<usize>> {
+        let (head, rest) = self.body.split_first()?;
+        if rest.len() + 1 != self.arguments.len() {
+            return None;
+        }
+        match head {
+            Atom::Reference { id, .. } if self.arguments.iter().any(|a| a.id == *id) => {
+                return None;
+            }
+            Atom::Reference { .. } | Atom::Builtin { .. } => {}
+            _ => return None,
+        }
+        let mut permutation = Vec::with_capacity(rest.len());
+        for atom in rest {
+            let Atom::Reference { id, .. } = atom else {
+                return None;
+            };
+            let index = self.arguments.iter().position(|a| a.id == *id)?;
+            if index == 0 || permutation.contains(&index) {
+                return None;
+            }
+            permutation.push(index);
+        }
+        Some(permutation)
+    }
+}
+
+impl<B: Clone> Program<B> {
+    /// Eta-reduction. Returns the number of references rewritten.
+    ///
+    /// A procedure like `f x y: g x y` only forwards its arguments, so every
+    /// reference to `f` is replaced by `g`. When the arguments are permuted, as
+    /// in `f x y: g y x`, calls `f a b` are rewritten to `g b a`. The
+    /// forwarding procedures are left for [`Program::tree_shake`] to
+    /// remove.
+    pub fn eta_reduce(&mut self) -> usize {
+        let mut changes = 0;
+        loop {
+            let mut round = 0;
+            for i in 0..self.procedures.len() {
+                let proc = &self.procedures[i];
+                let Some(permutation) = proc.forwarding() else {
+                    continue;
+                };
+                let id = proc.id();
+                let target = proc.body[0].clone();
+                if matches!(target, Atom::Reference { id: t, .. } if t == id) {
+                    continue;
+                }
+                if permutation.iter().enumerate().all(|(i, j)| i + 1 == *j) {
+                    round += self.replace(id, &target);
+                } else {
+                    round += self.rewrite_calls(id, &target, &permutation);
+                }
+            }
+            if round == 0 {
+                return changes;
+            }
+            changes += round;
+        }
+    }
+
+    /// Rewrite calls `id a_1 … a_n` to `target a_p(1) … a_p(n)`.
+    fn rewrite_calls(&mut self, id: u32, target: &Atom<B>, permutation: &[usize]) -> usize {
+        let mut rewritten = 0;
+        for proc in &mut self.procedures {
+            if !matches!(proc.body.first(), Some(Atom::Reference { id: r, .. }) if *r == id)
+                || proc.body.len() != permutation.len() + 1
+            {
+                continue;
+            }
+            let mut body = Vec::with_capacity(proc.body.len());
+            body.push(target.clone());
+            body.extend(permutation.iter().map(|j| proc.body[*j].clone()));
+            proc.body = body;
+            rewritten += 1;
+        }
+        rewritten
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ir::format_procedure, tests::program};
+
+    /// Eta-reduce and return the reduced `main`.
+    fn reduce(source: &str) -> (usize, String) {
+        let mut program = program(source);
+        let changes = program.eta_reduce();
+        let main = program.procedure_by_name("main").unwrap();
+        (changes, format_procedure(&program, main))
+    }
+
+    #[test]
+    fn forwarding() {
+        let source = "main exit: f 1 2 exit\nf a b ret: g a b ret\ng a b ret: sub a b ret\n";
+        assert_eq!(
+            reduce(source),
+            (3, "main_0 exit_1: @sub 1 2 exit_1\n".to_string())
+        );
+    }
+
+    #[test]
+    fn permutation() {
+        let source = "main exit: f 1 2 exit\nf a b ret: g b a ret\ng a b ret: sub a b ret\n";
+        assert_eq!(reduce(source).1, "main_0 exit_1: @sub 2 1 exit_1\n");
+    }
+
+    #[test]
+    fn repeated_argument() {
+        let source = "main exit: f 1 2 exit\nf a b ret: g a a ret\ng a b ret: sub a b ret\n";
+        assert_eq!(reduce(source).1, "main_0 exit_1: f_2 1 2 exit_1\n");
+    }
+
+    #[test]
+    fn self_call() {
+        let source = "main exit: f exit\nf ret: f ret\n";
+        assert_eq!(
+            reduce(source),
+            (0, "main_0 exit_1: f_2 exit_1\n".to_string())
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Constant folding and partial evaluation of builtins.
+
+use {
+    super::{Atom, Program},
+    crate::front::Span,
+};
+
+/// Compile time semantics of builtins.
+pub trait Fold: Sized {
+    /// A pure builtin has no effect other than calling one of its arguments.
+    fn is_pure(&self) -> bool;
+
+    /// Evaluate a call to this builtin. Returns the call that replaces it, or
+    /// `None` if the arguments are not sufficiently known. `arguments` does not
+    /// include the builtin itself and `source` is the span to use for new
+    /// atoms.
+    fn fold(&self, source: Span, arguments: &[Atom<Self>]) -> Option<Vec<Atom<Self>>>;
+}
+
+impl<B> Atom<B> {
+    /// Literal values.
+    #[must_use]
+    pub const fn is_constant(&self) -> bool {
+        matches!(self, Self::Number { .. } | Self::String { .. })
+    }
+
+    /// Equality of literal values, ignoring the source.
+    fn same_constant(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Number { value: a, .. }, Self::Number { value: b, .. }) => a == b,
+            (Self::String { value: a, .. }, Self::String { value: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<B: Fold + Clone> Program<B> {
+    /// Constant folding. Returns the number of folded calls plus the number of
+    /// parameters replaced by a constant.
+    ///
+    /// Calls to pure builtins with known arguments are evaluated, and constants
+    /// passed to a parameter at every call site are propagated into the body.
+    /// The `root` procedure is called from outside the program, so nothing is
+    /// propagated into its parameters.
+    pub fn constant_fold(&mut self, root: u32) -> usize {
+        let mut changes = 0;
+        loop {
+            let round = self.fold_calls() + self.propagate_constants(root);
+            if round == 0 {
+                return changes;
+            }
+            changes += round;
+        }
+    }
+
+    fn fold_calls(&mut self) -> usize {
+        let mut folded = 0;
+        for proc in &mut self.procedures {
+            let Some(Atom::Builtin { source, builtin }) = proc.body.first() else {
+                continue;
+            };
+            if !builtin.is_pure() {
+                continue;
+            }
+            if let Some(body) = builtin.fold(*source, &proc.body[1..]) {
+                proc.body = body;
+                folded += 1;
+            }
+        }
+        folded
+    }
+
+    fn propagate_constants(&mut self, root: u32) -> usize {
+        // Find parameters that receive the same constant at every call site.
+        let mut constants = vec![];
+        for callee in &self.procedures {
+            let id = callee.id();
+            if id == root {
+                continue;
+            }
+            let mut sites = vec![];
+            let mut escapes = false;
+            for proc in &self.procedures {
+                for (i, atom) in proc.body.iter().enumerate() {
+                    if matches!(atom, Atom::Reference { id: r, .. } if *r == id) {
+                        if i == 0 {
+                            sites.push(&proc.body);
+                        } else {
+                            escapes = true;
+                        }
+                    }
+                }
+            }
+            if escapes || sites.is_empty() {
+                continue;
+            }
+            if sites.iter().any(|s| s.len() != callee.arguments.len()) {
+                continue;
+            }
+            for (i, parameter) in callee.arguments.iter().enumerate().skip(1) {
+                let constant = &sites[0][i];
+                if constant.is_constant() && sites.iter().all(|s| s[i].same_constant(constant)) {
+                    constants.push((parameter.id, constant.clone()));
+                }
+            }
+        }
+
+        // Replace references, this includes references captured by closures.
+        let mut propagated = 0;
+        for (id, constant) in constants {
+            if self.replace(id, &constant) > 0 {
+                propagated += 1;
+            }
+        }
+        propagated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ir::format_procedure, tests::program};
+
+    /// Constant fold and return the folded procedure `name`.
+    fn fold(source: &str, name: &str) -> (usize, String) {
+        let mut program = program(source);
+        let main = program.procedure_by_name("main").unwrap().id();
+        let changes = program.constant_fold(main);
+        let proc = program.procedure_by_name(name).unwrap();
+        (changes, format_procedure(&program, proc))
+    }
+
+    #[test]
+    fn propagate_and_fold() {
+        // `a` is 2 at every call, so `mul 2 3` folds to 6.
+        let source = "main exit: f 2 exit\nf a ret: mul a 3 ret\n";
+        assert_eq!(
+            fold(source, "f"),
+            (2, "f_2 a_3 ret_4: ret_4 6\n".to_string())
+        );
+    }
+
+    #[test]
+    fn impure_builtins() {
+        let source = "main exit: f 2 exit\nf a ret: print “hi” (: ret a)\n";
+        let (changes, f) = fold(source, "f");
+        assert_eq!(changes, 1);
+        assert_eq!(f, "f_2 a_3 ret_4: @print \"hi\" _5\n");
+    }
+
+    #[test]
+    fn different_constants() {
+        let source = "main exit: f 2 exit\nf a ret: mul a 3 ret\ng ret: f 4 ret\n";
+        assert_eq!(fold(source, "f").0, 0);
+    }
+
+    #[test]
+    fn escaping_procedure() {
+        // `f` is passed to `h`, so not all its calls are known.
+        let source = "main exit: f 2 exit\nf a ret: ret a\ng ret: h f\nh k: k 1 exit\n";
+        assert_eq!(fold(source, "f").0, 0);
+    }
+
+    #[test]
+    fn root_parameters() {
+        // `exit` is 1 at the only call, but `main` is also called from outside.
+        let source = "main exit: exit 0\ncall: main 1\n";
+        assert_eq!(fold(source, "main").0, 0);
+    }
+}
@@ -0,0 +1,276 @@
+//! Closure conversion to a first-order representation.
+//!
+//! Every function receives its closure as an explicit environment record and
+//! closure creation is an explicit allocation. With lambda lifting, procedures
+//! that are only ever called directly get their captures as extra parameters
+//! instead, so no closure is allocated for them.
+
+use {
+    super::{Atom, Identifier, Number, Procedure, Program},
+    crate::front::Span,
+    core::fmt::Display,
+};
+
+/// A closure converted program.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Lowered<B> {
+    pub source:    String,
+    pub functions: Vec<Function<B>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Function<B> {
+    pub source:      Span,
+    /// The procedure this function implements.
+    pub name:        Identifier,
+    /// Variables loaded from the environment record.
+    pub environment: Vec<u32>,
+    /// Captured variables passed as leading parameters.
+    pub lifted:      Vec<u32>,
+    pub parameters:  Vec<Identifier>,
+    /// Closures allocated before the call, in order.
+    pub allocations: Vec<Allocation<B>>,
+    pub call:        Call<B>,
+}
+
+/// Allocation of a closure record.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Allocation<B> {
+    /// Fresh variable holding the closure.
+    pub variable: u32,
+    pub function: u32,
+    pub captures: Vec<Operand<B>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Operand<B> {
+    /// A parameter, environment entry or allocated closure.
+    Variable(u32),
+    /// The closure of the function itself.
+    Current,
+    Builtin(B),
+    Number(Number),
+    String(String),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Call<B> {
+    /// Call a closure value, passing it its environment record.
+    Indirect {
+        callee:    Operand<B>,
+        arguments: Vec<Operand<B>>,
+    },
+    /// Call a lifted function, the arguments start with its captures.
+    Direct {
+        function:  u32,
+        arguments: Vec<Operand<B>>,
+    },
+}
+
+impl<B> Lowered<B> {
+    #[must_use]
+    pub fn function_by_id(&self, id: u32) -> Option<&Function<B>> {
+        self.functions.iter().find(|f| f.name.id == id)
+    }
+}
+
+impl<B: Clone> Program<B> {
+    /// Closure conversion. With `lift`, procedures other than `root` that are
+    /// only ever called directly are lambda lifted. Requires closures to be up
+    /// to date.
+    #[must_use]
+    pub fn closure_convert(&self, root: u32, lift: bool) -> Lowered<B> {
+        let lifted = self
+            .procedures
+            .iter()
+            .map(Procedure::id)
+            .filter(|id| lift && *id != root && !self.escapes(*id))
+            .collect::<Vec<_>>();
+        let mut fresh = self.identifiers().map(|i| i.id + 1).max().unwrap_or(0);
+
+        let functions = self
+            .procedures
+            .iter()
+            .map(|proc| {
+                let is_lifted = lifted.contains(&proc.id());
+                let mut allocations = vec![];
+                let mut operands = proc
+                    .body
+                    .iter()
+                    .enumerate()
+                    .map(|(position, atom)| match atom {
+                        Atom::Builtin { builtin, .. } => Operand::Builtin(builtin.clone()),
+                        Atom::Number { value, .. } => Operand::Number(value.clone()),
+                        Atom::String { value, .. } => Operand::String(value.clone()),
+                        Atom::Reference { id, .. } if *id == proc.id() => Operand::Current,
+                        Atom::Reference { id, .. } => {
+                            // Lifted functions only appear in call position.
+                            let Some(callee) = self.procedure_by_id(*id) else {
+                                return Operand::Variable(*id);
+                            };
+                            if lifted.contains(id) {
+                                debug_assert_eq!(position, 0);
+                                return Operand::Variable(*id);
+                            }
+                            let variable = fresh;
+                            fresh += 1;
+                            allocations.push(Allocation {
+                                variable,
+                                function: *id,
+                                captures: callee
+                                    .closure
+                                    .iter()
+                                    .copied()
+                                    .map(Operand::Variable)
+                                    .collect(),
+                            });
+                            Operand::Variable(variable)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let callee = operands.remove(0);
+                let call = match proc.body[0] {
+                    Atom::Reference { id, .. } if lifted.contains(&id) => {
+                        let captures = &self.procedure_by_id(id).unwrap().closure;
+                        let mut arguments = captures
+                            .iter()
+                            .copied()
+                            .map(Operand::Variable)
+                            .collect::<Vec<_>>();
+                        arguments.extend(operands);
+                        Call::Direct {
+                            function: id,
+                            arguments,
+                        }
+                    }
+                    _ => Call::Indirect {
+                        callee,
+                        arguments: operands,
+                    },
+                };
+
+                let (environment, captured) = if is_lifted {
+                    (vec![], proc.closure.clone())
+                } else {
+                    (proc.closure.clone(), vec![])
+                };
+                Function {
+                    source: proc.source,
+                    name: *proc.name(),
+                    environment,
+                    lifted: captured,
+                    parameters: proc.arguments[1..].to_vec(),
+                    allocations,
+                    call,
+                }
+            })
+            .collect();
+
+        Lowered {
+            source: self.source.clone(),
+            functions,
+        }
+    }
+
+    /// Whether a procedure is used other than as the target of a call.
+    fn escapes(&self, id: u32) -> bool {
+        self.procedures.iter().any(|p| {
+            p.body
+                .iter()
+                .skip(1)
+                .any(|atom| matches!(atom, Atom::Reference { id: r, .. } if *r == id))
+        })
+    }
+}
+
+pub fn pretty_print_lowered<B: Display>(program: &Lowered<B>) {
+    let name = |id: u32| {
+        program
+            .functions
+            .iter()
+            .flat_map(|f| f.parameters.iter().chain([&f.name]))
+            .find(|i| i.id == id && i.named)
+            .map_or_else(
+                || format!("_{id}"),
+                |i| format!("{}_{id}", &program.source[i.source.start..i.source.end]),
+            )
+    };
+    let operand = |operand: &Operand<B>| match operand {
+        Operand::Variable(id) => name(*id),
+        Operand::Current => "self".to_string(),
+        Operand::Builtin(builtin) => format!("@{builtin}"),
+        Operand::Number(value) => format!("{value}"),
+        Operand::String(value) => format!("{value:?}"),
+    };
+    let list = |ids: &[u32]| ids.iter().map(|id| name(*id)).collect::<Vec<_>>().join(" ");
+    for function in &program.functions {
+        eprint!("{}", name(function.name.id));
+        if !function.lifted.is_empty() {
+            eprint!(" [{}]", list(&function.lifted));
+        }
+        for parameter in &function.parameters {
+            eprint!(" {}", name(parameter.id));
+        }
+        if !function.environment.is_empty() {
+            eprint!(" {{{}}}", list(&function.environment));
+        }
+        eprint!(":");
+        for allocation in &function.allocations {
+            eprint!(
+                " {} = {}{{",
+                name(allocation.variable),
+                name(allocation.function)
+            );
+            let captures = allocation.captures.iter().map(operand).collect::<Vec<_>>();
+            eprint!("{}}};", captures.join(" "));
+        }
+        let (callee, arguments) = match &function.call {
+            Call::Indirect { callee, arguments } => (operand(callee), arguments),
+            Call::Direct {
+                function,
+                arguments,
+            } => (format!("{}!", name(*function)), arguments),
+        };
+        eprint!(" {callee}");
+        for argument in arguments {
+            eprint!(" {}", operand(argument));
+        }
+        eprintln!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::tests::program};
+
+    const SOURCE: &str = "main exit: f 1 (x: exit x)\nf a ret: add a 1 ret\n";
+
+    #[test]
+    fn allocations() {
+        let program = program(SOURCE);
+        let main = program.procedure_by_name("main").unwrap();
+        let lowered = program.closure_convert(main.id(), false);
+        let main = lowered.function_by_id(main.id()).unwrap();
+        // `main` allocates `f` without captures and the continuation with `exit`.
+        let captures = main.allocations.iter().map(|a| a.captures.len());
+        assert_eq!(captures.collect::<Vec<_>>(), [0, 1]);
+        assert!(matches!(main.call, Call::Indirect { .. }));
+    }
+
+    #[test]
+    fn lambda_lifting() {
+        let program = program(SOURCE);
+        let main = program.procedure_by_name("main").unwrap();
+        let f = program.procedure_by_name("f").unwrap().id();
+        let lowered = program.closure_convert(main.id(), true);
+        let main = lowered.function_by_id(main.id()).unwrap();
+        // `f` is only called, so it is not allocated. The continuation escapes.
+        assert_eq!(main.allocations.len(), 1);
+        assert!(matches!(main.call, Call::Direct { function, .. } if function == f));
+        let continuation = &main.allocations[0];
+        let continuation = lowered.function_by_id(continuation.function).unwrap();
+        assert_eq!(continuation.environment.len(), 1);
+        assert!(continuation.lifted.is_empty());
+    }
+}
@@ -0,0 +1,574 @@
+//! Intermediate Representation
+
+mod cfa;
+mod eta;
+mod fold;
+mod lower;
+mod number;
+mod pass;
+mod verify;
+
+pub use self::{
+    cfa::{ArityMismatch, Callee, ControlFlow, Flow, Flowing},
+    fold::Fold,
+    lower::{Allocation, Call, Function, Lowered, Operand, pretty_print_lowered},
+    number::Number,
+    pass::{
+        ClosureAnalysis, ConstantFold, Deduplicate, Devirtualize, EtaReduce, Inline, OptLevel,
+        Pass, PassError, PassManager, Statistics, TreeShake,
+    },
+    verify::VerifyError,
+};
+use {
+    crate::front::Span,
+    core::fmt,
+    petgraph::{
+        algo::{condensation, toposort},
+        graph::{DiGraph, NodeIndex},
+    },
+    std::mem::swap,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Identifier {
+    pub source:     Span,
+    pub named:      bool,
+    pub id:         u32,
+    /// Source of the type annotation, including brackets.
+    pub annotation: Option<Span>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Atom<B> {
+    Builtin { source: Span, builtin: B },
+    Reference { source: Span, id: u32 },
+    String { source: Span, value: String },
+    Number { source: Span, value: Number },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Procedure<B> {
+    pub source:    Span,
+    pub arguments: Vec<Identifier>,
+    pub closure:   Vec<u32>,
+    pub body:      Vec<Atom<B>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Program<B> {
+    pub source:     String,
+    pub procedures: Vec<Procedure<B>>,
+    /// Declared data types, for type checking.
+    pub data:       Vec<Data>,
+    /// Tests declared with `test “name” call…`.
+    pub tests:      Vec<Test>,
+}
+
+/// A data type declaration. Values are Scott encoded: a value is a procedure
+/// taking one case per constructor, in declaration order, and calls the case
+/// of its constructor with the fields.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Data {
+    pub source:       Span,
+    pub name:         String,
+    pub constructors: Vec<Constructor>,
+}
+
+/// A test, the procedure `test done: call… done`. It passes when it calls
+/// `done`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test {
+    pub source: Span,
+    pub name:   String,
+    pub id:     u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Constructor {
+    /// Procedure `name fields… ret: ret value`.
+    pub id:     u32,
+    /// Procedure `value cases…: case fields…`.
+    pub value:  u32,
+    pub fields: usize,
+}
+
+impl<B> Atom<B> {
+    #[must_use]
+    pub const fn source(&self) -> Span {
+        match self {
+            Self::Builtin { source, .. }
+            | Self::Reference { source, .. }
+            | Self::String { source, .. }
+            | Self::Number { source, .. } => *source,
+        }
+    }
+}
+
+impl<B> Procedure<B> {
+    #[must_use]
+    pub fn name(&self) -> &Identifier {
+        self.arguments
+            .first()
+            .expect("ICE: Every procedure must have name as first argument.")
+    }
+
+    #[must_use]
+    pub fn id(&self) -> u32 {
+        self.name().id
+    }
+}
+
+impl<B> Program<B> {
+    #[must_use]
+    pub fn procedure_by_id(&self, id: u32) -> Option<&Procedure<B>> {
+        self.procedures.iter().find(|p| p.id() == id)
+    }
+
+    #[must_use]
+    pub fn identifiers(&self) -> impl Iterator<Item = &Identifier> {
+        self.procedures
+            .iter()
+            .flat_map(|proc| proc.arguments.iter())
+    }
+
+    #[must_use]
+    pub fn string(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+
+    #[must_use]
+    pub fn id_string(&self, id: u32) -> Option<&str> {
+        self.identifiers().find(|i| i.id == id).and_then(|i| {
+            if i.named {
+                Some(self.string(i.source))
+            } else {
+                None
+            }
+        })
+    }
+
+    #[must_use]
+    pub fn procedure_by_name(&self, name: &str) -> Option<&Procedure<B>> {
+        self.procedures
+            .iter()
+            .find(|p| self.string(p.name().source) == name)
+    }
+
+    /// Construct a graph of closure dependencies.
+    #[must_use]
+    pub fn closure_graph(&self) -> DiGraph<usize, ()> {
+        let mut graph = DiGraph::with_capacity(self.procedures.len(), 0);
+        for i in 0..self.procedures.len() {
+            graph.add_node(i);
+        }
+        for (i, proc) in self.procedures.iter().enumerate() {
+            let from = NodeIndex::from(i as u32);
+            for atom in &proc.body {
+                // If it references a procedure name, we need to add a closure dependency.
+                if let Atom::Reference { id, .. } = atom {
+                    if let Some(j) = self.procedures.iter().position(|p| p.id() == *id) {
+                        let to = NodeIndex::from(j as u32);
+                        graph.add_edge(from, to, ());
+                    }
+                }
+            }
+        }
+        graph
+    }
+
+    /// Perform closure analysis on the program.
+    ///
+    /// Closures are recomputed from scratch, so this can be re-run after any
+    /// transformation that changed procedure bodies.
+    pub fn closure_analysis(&mut self) {
+        for proc in &mut self.procedures {
+            proc.closure.clear();
+        }
+
+        // Solve recursion.
+        // Iterate over the closure graph in condensed topological order. This means to
+        // consider strongly connected components together, and work backwards
+        // from visited nodes.
+        let graph = self.closure_graph();
+        let condensed = condensation(graph, true);
+        let order = toposort(&condensed, None).expect("Condensation is acyclic.");
+        for node in order.iter().rev() {
+            let component = condensed.node_weight(*node).unwrap();
+
+            // Iterate on the component until convergence.
+            let mut converged = false;
+            while !converged {
+                converged = true;
+                for proc_index in component {
+                    let proc = &self.procedures[*proc_index];
+                    let contains = |id: u32| {
+                        proc.arguments.iter().any(|arg| arg.id == id) || proc.closure.contains(&id)
+                    };
+                    let mut to_add = vec![];
+                    for atom in &proc.body {
+                        let Atom::Reference { id, .. } = atom else {
+                            continue;
+                        };
+                        if contains(*id) {
+                            continue;
+                        }
+                        if let Some(c) = self.procedures.iter().find(|p| p.id() == *id) {
+                            for item in &c.closure {
+                                if !contains(*item) {
+                                    to_add.push(*item);
+                                }
+                            }
+                        } else {
+                            to_add.push(*id);
+                        }
+                    }
+                    if !to_add.is_empty() {
+                        converged = false;
+                        self.procedures[*proc_index].closure.extend(&to_add);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove unreachable procedures. Returns the number of procedures removed.
+    pub fn tree_shake(&mut self, root: u32) -> usize {
+        let mut live = vec![false; self.procedures.len()];
+        let root_ix = self
+            .procedures
+            .iter()
+            .position(|p| p.id() == root)
+            .expect("Root not found.");
+        let mut stack = vec![root_ix];
+        while let Some(ix) = stack.pop() {
+            if live[ix] {
+                continue;
+            }
+            live[ix] = true;
+            for atom in &self.procedures[ix].body {
+                let Atom::Reference { id, .. } = atom else {
+                    continue;
+                };
+                if let Some(j) = self.procedures.iter().position(|p| p.id() == *id) {
+                    stack.push(j);
+                }
+            }
+        }
+        let mut procedures = vec![];
+        swap(&mut procedures, &mut self.procedures);
+        self.procedures = procedures
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| live[*i])
+            .map(|(_, p)| p)
+            .collect();
+        live.iter().filter(|l| !**l).count()
+    }
+
+    /// Total number of atoms in all procedure bodies.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.procedures.iter().map(|p| p.body.len()).sum()
+    }
+
+    /// Replace all references to `from` by references to `to`.
+    pub fn substitute(&mut self, from: u32, to: u32) {
+        for proc in &mut self.procedures {
+            for atom in &mut proc.body {
+                if let Atom::Reference { id, .. } = atom {
+                    if *id == from {
+                        *id = to;
+                    }
+                }
+            }
+            for id in &mut proc.closure {
+                if *id == from {
+                    *id = to;
+                }
+            }
+        }
+    }
+}
+
+impl<B: Clone> Program<B> {
+    /// Replace all references to `id` in procedure bodies by `atom`. Returns
+    /// the number of references replaced.
+    pub fn replace(&mut self, id: u32, atom: &Atom<B>) -> usize {
+        let mut replaced = 0;
+        for proc in &mut self.procedures {
+            for old in &mut proc.body {
+                if matches!(old, Atom::Reference { id: r, .. } if *r == id) {
+                    *old = atom.clone();
+                    replaced += 1;
+                }
+            }
+        }
+        replaced
+    }
+}
+
+impl<B: PartialEq> Program<B> {
+    /// Collapse duplicate procedures. Returns the number of procedures removed.
+    ///
+    /// Two procedures are duplicates if they have the same closure and their
+    /// bodies are equal up to renaming of arguments. Requires closures to be
+    /// up to date.
+    ///
+    /// Procedures capturing an argument of a removed duplicate can only be
+    /// created inside it, so they are removed along with it.
+    pub fn deduplicate(&mut self) -> usize {
+        let count = self.procedures.len();
+        // Merging two procedures can make their callers equal, so repeat.
+        loop {
+            let mut removed = vec![false; self.procedures.len()];
+            let mut merges = vec![];
+            for i in 0..self.procedures.len() {
+                if removed[i] {
+                    continue;
+                }
+                let proc = &self.procedures[i];
+                for (j, other) in self.procedures.iter().enumerate().skip(i + 1) {
+                    if !removed[j] && proc.alpha_eq(other) {
+                        removed[j] = true;
+                        merges.push((other.id(), proc.id()));
+                    }
+                }
+            }
+            if merges.is_empty() {
+                return count - self.procedures.len();
+            }
+
+            // Remove the procedures nested in the duplicates, and those nested
+            // in them.
+            let mut unbound = vec![];
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for (proc, removed) in self.procedures.iter().zip(&mut removed) {
+                    if !*removed && proc.closure.iter().any(|id| unbound.contains(id)) {
+                        *removed = true;
+                    }
+                    if *removed && !unbound.contains(&proc.id()) {
+                        unbound.extend(proc.arguments.iter().map(|arg| arg.id));
+                        changed = true;
+                    }
+                }
+            }
+
+            let mut index = 0;
+            self.procedures.retain(|_| {
+                index += 1;
+                !removed[index - 1]
+            });
+            for (duplicate, keep) in merges {
+                self.substitute(duplicate, keep);
+            }
+        }
+    }
+}
+
+impl<B: PartialEq> Procedure<B> {
+    /// Structural equality up to renaming of arguments.
+    fn alpha_eq(&self, other: &Self) -> bool {
+        if self.arguments.len() != other.arguments.len()
+            || self.closure != other.closure
+            || self.body.len() != other.body.len()
+        {
+            return false;
+        }
+        let position = |proc: &Self, id: u32| proc.arguments.iter().position(|a| a.id == id);
+        self.body.iter().zip(&other.body).all(|pair| match pair {
+            (Atom::Builtin { builtin: a, .. }, Atom::Builtin { builtin: b, .. }) => a == b,
+            (Atom::String { value: a, .. }, Atom::String { value: b, .. }) => a == b,
+            (Atom::Number { value: a, .. }, Atom::Number { value: b, .. }) => a == b,
+            (Atom::Reference { id: a, .. }, Atom::Reference { id: b, .. }) => {
+                match (position(self, *a), position(other, *b)) {
+                    (Some(a), Some(b)) => a == b,
+                    (None, None) => a == b,
+                    _ => false,
+                }
+            }
+            _ => false,
+        })
+    }
+}
+
+impl<B: Clone> Program<B> {
+    /// Inlining of procedures. Returns the number of calls inlined.
+    ///
+    /// Any procedure that calls a known procedure can be inlined. To avoid
+    /// remapping captures, only procedures with an empty closure that refer to
+    /// nothing but their own arguments and other closure-free procedures are
+    /// inlined. Requires closures to be up to date.
+    pub fn inline(&mut self) -> usize {
+        // Maximum number of successive inlinings in a single body, this aborts
+        // on cycles.
+        const MAX_DEPTH: usize = 16;
+
+        let mut inlined = 0;
+        for i in 0..self.procedures.len() {
+            let id = self.procedures[i].id();
+            let mut body = vec![];
+            swap(&mut self.procedures[i].body, &mut body);
+            // Repeatedly inline the call in the body.
+            for _ in 0..MAX_DEPTH {
+                let Some(Atom::Reference { id: callee, .. }) = body.first() else {
+                    break;
+                };
+                // The body of procedure `i` is taken out, so it can not be inlined
+                // into itself.
+                let Some(call) = self.procedure_by_id(*callee).filter(|p| p.id() != id) else {
+                    break;
+                };
+                if call.arguments.len() != body.len() || !self.is_inlinable(call) {
+                    break;
+                }
+                // Inline the known call, mapping the arguments to the call.
+                body = call
+                    .body
+                    .iter()
+                    .map(|atom| {
+                        if let Atom::Reference { id, .. } = atom {
+                            if let Some(j) = call.arguments.iter().position(|arg| arg.id == *id) {
+                                return body[j].clone();
+                            }
+                        }
+                        atom.clone()
+                    })
+                    .collect();
+                inlined += 1;
+            }
+            swap(&mut self.procedures[i].body, &mut body);
+        }
+        inlined
+    }
+
+    fn is_inlinable(&self, proc: &Procedure<B>) -> bool {
+        proc.closure.is_empty()
+            && proc.body.iter().all(|atom| {
+                let Atom::Reference { id, .. } = atom else {
+                    return true;
+                };
+                proc.arguments.iter().any(|arg| arg.id == *id)
+                    || self
+                        .procedure_by_id(*id)
+                        .is_some_and(|p| p.closure.is_empty())
+            })
+    }
+}
+
+pub fn pretty_print_ir<B>(program: &Program<B>) {
+    eprint!("{}", format_ir(program));
+}
+
+pub fn pretty_print_procedure<B>(program: &Program<B>, proc: &Procedure<B>) {
+    eprint!("{}", format_procedure(program, proc));
+}
+
+/// The IR of a program as text, one procedure per line.
+#[must_use]
+pub fn format_ir<B>(program: &Program<B>) -> String {
+    let mut out = String::new();
+    for proc in &program.procedures {
+        write_procedure(&mut out, program, proc).expect("Writing to a string never fails.");
+    }
+    out
+}
+
+#[must_use]
+pub fn format_procedure<B>(program: &Program<B>, proc: &Procedure<B>) -> String {
+    let mut out = String::new();
+    write_procedure(&mut out, program, proc).expect("Writing to a string never fails.");
+    out
+}
+
+fn write_procedure<B>(
+    out: &mut impl fmt::Write,
+    program: &Program<B>,
+    proc: &Procedure<B>,
+) -> fmt::Result {
+    for (i, arg) in proc.arguments.iter().enumerate() {
+        if let Some(name) = program.id_string(arg.id) {
+            write!(out, "{name}_{}", arg.id)?;
+        } else {
+            write!(out, "_{}", arg.id)?;
+        }
+        if let Some(annotation) = arg.annotation {
+            write!(out, "{}", program.string(annotation))?;
+        }
+        if i != proc.arguments.len() - 1 {
+            write!(out, " ")?;
+        }
+    }
+    if !proc.closure.is_empty() {
+        write!(out, "; ")?;
+        for (i, arg) in proc.closure.iter().enumerate() {
+            if let Some(name) = program.id_string(*arg) {
+                write!(out, "{name}_{}", *arg)?;
+            } else {
+                write!(out, "_{}", *arg)?;
+            }
+            if i != proc.closure.len() - 1 {
+                write!(out, " ")?;
+            }
+        }
+    }
+    write!(out, ":")?;
+    for a in &proc.body {
+        write!(out, " ")?;
+        match a {
+            Atom::Builtin { source, .. } => {
+                write!(out, "@{}", program.string(*source))?;
+            }
+            Atom::Number { value, .. } => write!(out, "{value}")?,
+            Atom::String { value, .. } => write!(out, "{value:?}")?,
+            Atom::Reference { id, .. } => {
+                if let Some(name) = program.id_string(*id) {
+                    write!(out, "{name}_{id}")?;
+                } else {
+                    write!(out, "_{id}")?;
+                }
+            }
+        }
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::program;
+
+    #[test]
+    fn tree_shake() {
+        let mut program = program("f ret: ret 1\ng ret: f ret\nmain exit: exit 0\n");
+        let main = program.procedure_by_name("main").unwrap().id();
+        assert_eq!(program.tree_shake(main), 2);
+        assert_eq!(program.procedures.len(), 1);
+    }
+
+    #[test]
+    fn deduplicate() {
+        let mut program = program(
+            "f a ret: add a 1 ret\ng b ret: add b 1 ret\nh a ret: f a ret\nk a ret: g a ret\n",
+        );
+        // Merging `f` and `g` makes `h` and `k` equal.
+        assert_eq!(program.deduplicate(), 2);
+        assert_eq!(program.procedures.len(), 2);
+        assert!(program.procedure_by_name("g").is_none());
+        program.verify().unwrap();
+    }
+
+    #[test]
+    fn deduplicate_removes_nested() {
+        let mut program = program("f a ret: ret 0\ng a ret: ret (x: a (y: x y))\n");
+        // Make `g` a duplicate of `f`, leaving the procedures nested in it unused.
+        let zero = program.procedure_by_name("f").unwrap().body[1].clone();
+        let g = program.procedure_by_name("g").unwrap().id();
+        let g = program.procedures.iter_mut().find(|p| p.id() == g).unwrap();
+        g.body[1] = zero;
+        program.closure_analysis();
+        assert_eq!(program.deduplicate(), 3);
+        assert_eq!(program.procedures.len(), 1);
+        program.verify().unwrap();
+    }
+}
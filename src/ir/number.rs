@@ -0,0 +1,217 @@
+//! Numbers are arbitrary precision integers or `f64` floats.
+//!
+//! Integer arithmetic is exact and never overflows. Mixing an integer with a
+//! float converts the integer to the nearest float and the result follows
+//! IEEE 754. Integer division and remainder round towards negative infinity;
+//! dividing an integer by zero is done as float division, so it gives an
+//! infinity or NaN instead of panicking.
+
+use {
+    core::{
+        cmp::Ordering,
+        fmt::{self, Debug, Display},
+        hash::{Hash, Hasher},
+        str::FromStr,
+    },
+    num_bigint::BigInt,
+    num_traits::{Signed, ToPrimitive, Zero},
+};
+
+#[derive(Clone)]
+pub enum Number {
+    Integer(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    #[must_use]
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Self::Integer(a) => a.is_zero(),
+            Self::Float(a) => *a == 0.0,
+        }
+    }
+
+    #[must_use]
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Self::Integer(a) => a.to_f64().unwrap_or(f64::NAN),
+            Self::Float(a) => *a,
+        }
+    }
+
+    /// Saturating conversion to an index. Floats are truncated and negative
+    /// numbers and NaN become zero.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn to_index(&self) -> usize {
+        match self {
+            Self::Integer(a) if a.is_negative() => 0,
+            Self::Integer(a) => a.to_usize().unwrap_or(usize::MAX),
+            Self::Float(a) => *a as usize,
+        }
+    }
+
+    #[must_use]
+    pub fn add(&self, other: &Self) -> Self {
+        self.binary(other, |a, b| a + b, |a, b| a + b)
+    }
+
+    #[must_use]
+    pub fn sub(&self, other: &Self) -> Self {
+        self.binary(other, |a, b| a - b, |a, b| a - b)
+    }
+
+    #[must_use]
+    pub fn mul(&self, other: &Self) -> Self {
+        self.binary(other, |a, b| a * b, |a, b| a * b)
+    }
+
+    /// Division, rounding integers towards negative infinity.
+    #[must_use]
+    pub fn div(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) if !b.is_zero() => {
+                let quotient = a / b;
+                if (a % b).is_zero() || a.is_negative() == b.is_negative() {
+                    Self::Integer(quotient)
+                } else {
+                    Self::Integer(quotient - 1)
+                }
+            }
+            _ => Self::Float(self.to_f64() / other.to_f64()),
+        }
+    }
+
+    /// Remainder with the sign of the divisor, matching [`Self::div`].
+    #[must_use]
+    pub fn rem(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) if !b.is_zero() => {
+                let remainder = a % b;
+                if remainder.is_zero() || a.is_negative() == b.is_negative() {
+                    Self::Integer(remainder)
+                } else {
+                    Self::Integer(remainder + b)
+                }
+            }
+            _ => {
+                let (a, b) = (self.to_f64(), other.to_f64());
+                let remainder = a % b;
+                if remainder == 0.0 || (remainder < 0.0) == (b < 0.0) {
+                    Self::Float(remainder)
+                } else {
+                    Self::Float(remainder + b)
+                }
+            }
+        }
+    }
+
+    fn binary(
+        &self,
+        other: &Self,
+        integer: impl FnOnce(&BigInt, &BigInt) -> BigInt,
+        float: impl FnOnce(f64, f64) -> f64,
+    ) -> Self {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => Self::Integer(integer(a, b)),
+            _ => Self::Float(float(self.to_f64(), other.to_f64())),
+        }
+    }
+}
+
+/// Floats compare bitwise, so `NaN` equals itself and `0.0` differs from
+/// `-0.0`. This is structural equality for the IR, not numeric equality.
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Number {}
+
+impl Hash for Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Self::Integer(a) => a.hash(state),
+            Self::Float(a) => a.to_bits().hash(state),
+        }
+    }
+}
+
+/// A total order consistent with equality: integers before floats, floats
+/// ordered by [`f64::total_cmp`].
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => a.cmp(b),
+            (Self::Float(a), Self::Float(b)) => a.total_cmp(b),
+            (Self::Integer(_), Self::Float(_)) => Ordering::Less,
+            (Self::Float(_), Self::Integer(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<u64> for Number {
+    fn from(value: u64) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<i64> for Number {
+    fn from(value: i64) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<bool> for Number {
+    fn from(value: bool) -> Self {
+        u64::from(value).into()
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+/// Parses number literals: integers like `-12` and floats like `1.5e-3`.
+impl FromStr for Number {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['.', 'e', 'E']) {
+            s.parse().map(Self::Float).map_err(|e| format!("{e}"))
+        } else {
+            s.parse().map(Self::Integer).map_err(|e| format!("{e}"))
+        }
+    }
+}
+
+/// Floats always print with a fraction or exponent, so the output reads back
+/// as a float.
+impl Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Integer(a) => write!(f, "{a}"),
+            Self::Float(a) => write!(f, "{a:?}"),
+        }
+    }
+}
+
+impl Debug for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
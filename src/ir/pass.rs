@@ -0,0 +1,356 @@
+//! Pass manager for transformations on the intermediate representation.
+
+use {
+    super::{Flow, Fold, Program, VerifyError},
+    core::{
+        fmt::{self, Display},
+        str::FromStr,
+    },
+    std::{
+        error::Error,
+        time::{Duration, Instant},
+    },
+};
+
+/// A named transformation of a [`Program`].
+pub trait Pass<B> {
+    fn name(&self) -> &'static str;
+
+    /// Run the pass and return the number of changes it made. What counts as
+    /// a change is up to the pass.
+    fn run(&mut self, program: &mut Program<B>) -> usize;
+}
+
+/// Optimisation level, selecting a pipeline of passes.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum OptLevel {
+    /// Only the passes required for evaluation.
+    O0,
+    /// Cheap clean-up passes.
+    #[default]
+    O1,
+    /// All optimisations.
+    O2,
+}
+
+/// What a single pass did.
+#[derive(Clone, Debug)]
+pub struct Statistics {
+    pub pass:       &'static str,
+    pub duration:   Duration,
+    pub changes:    usize,
+    pub procedures: (usize, usize),
+    pub atoms:      (usize, usize),
+}
+
+/// Verification failure after running a pass.
+#[derive(Clone, Debug)]
+pub struct PassError {
+    pub pass:  &'static str,
+    pub error: VerifyError,
+}
+
+/// Runs a pipeline of passes, collecting statistics and optionally verifying
+/// the program after each pass.
+pub struct PassManager<B> {
+    root:       u32,
+    passes:     Vec<Box<dyn Pass<B>>>,
+    verify:     bool,
+    statistics: Vec<Statistics>,
+}
+
+/// Remove procedures not reachable from the root.
+pub struct TreeShake {
+    pub root: u32,
+}
+
+/// Recompute the closures of all procedures.
+pub struct ClosureAnalysis;
+
+/// Collapse duplicate procedures.
+pub struct Deduplicate;
+
+/// Inline calls to known procedures.
+pub struct Inline;
+
+/// Use k-CFA to turn indirect calls into direct ones and to cut off code that
+/// is never called.
+pub struct Devirtualize {
+    pub root: u32,
+    pub k:    usize,
+}
+
+/// Replace forwarding procedures by their target.
+pub struct EtaReduce;
+
+/// Evaluate pure builtins on constants and propagate constants into
+/// parameters.
+pub struct ConstantFold {
+    pub root: u32,
+}
+
+impl<B: Clone> PassManager<B> {
+    /// An empty pipeline for a program whose entry point is `root`.
+    #[must_use]
+    pub fn new(root: u32) -> Self {
+        Self {
+            root,
+            passes: Vec::new(),
+            verify: false,
+            statistics: Vec::new(),
+        }
+    }
+
+    /// Verify the program after every pass.
+    #[must_use]
+    pub const fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Append a pass to the pipeline.
+    pub fn add(&mut self, pass: impl Pass<B> + 'static) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Run all passes in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first pass after which verification fails.
+    pub fn run(&mut self, program: &mut Program<B>) -> Result<(), PassError> {
+        if self.verify {
+            program
+                .verify_scopes(self.root)
+                .map_err(|error| PassError {
+                    pass: "input",
+                    error,
+                })?;
+        }
+        for pass in &mut self.passes {
+            let procedures = program.procedures.len();
+            let atoms = program.size();
+            let start = Instant::now();
+            let changes = pass.run(program);
+            self.statistics.push(Statistics {
+                pass: pass.name(),
+                duration: start.elapsed(),
+                changes,
+                procedures: (procedures, program.procedures.len()),
+                atoms: (atoms, program.size()),
+            });
+            if self.verify {
+                program
+                    .verify_scopes(self.root)
+                    .map_err(|error| PassError {
+                        pass: pass.name(),
+                        error,
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Statistics of all passes run so far.
+    #[must_use]
+    pub fn statistics(&self) -> &[Statistics] {
+        &self.statistics
+    }
+}
+
+impl<B: Clone + PartialEq + Fold + Flow> PassManager<B> {
+    /// The standard pipeline for an optimisation level. Every pipeline ends
+    /// with closure analysis so the result can be evaluated.
+    #[must_use]
+    pub fn with_level(root: u32, level: OptLevel) -> Self {
+        let mut manager = Self::new(root);
+        manager.add(TreeShake { root });
+        if level >= OptLevel::O1 {
+            manager
+                .add(EtaReduce)
+                .add(ConstantFold { root })
+                .add(ClosureAnalysis)
+                .add(Deduplicate);
+        }
+        if level >= OptLevel::O2 {
+            manager
+                .add(ClosureAnalysis)
+                .add(Devirtualize { root, k: 0 })
+                .add(ClosureAnalysis)
+                .add(Inline);
+        }
+        if level >= OptLevel::O1 {
+            manager.add(TreeShake { root });
+        }
+        manager.add(ClosureAnalysis);
+        manager
+    }
+}
+
+impl<B> Pass<B> for TreeShake {
+    fn name(&self) -> &'static str {
+        "tree-shake"
+    }
+
+    fn run(&mut self, program: &mut Program<B>) -> usize {
+        program.tree_shake(self.root)
+    }
+}
+
+impl<B> Pass<B> for ClosureAnalysis {
+    fn name(&self) -> &'static str {
+        "closure-analysis"
+    }
+
+    /// Returns the number of closures that changed.
+    fn run(&mut self, program: &mut Program<B>) -> usize {
+        let before = program
+            .procedures
+            .iter()
+            .map(|p| p.closure.clone())
+            .collect::<Vec<_>>();
+        program.closure_analysis();
+        program
+            .procedures
+            .iter()
+            .zip(before)
+            .filter(|(p, closure)| p.closure != *closure)
+            .count()
+    }
+}
+
+impl<B: PartialEq> Pass<B> for Deduplicate {
+    fn name(&self) -> &'static str {
+        "deduplicate"
+    }
+
+    fn run(&mut self, program: &mut Program<B>) -> usize {
+        program.deduplicate()
+    }
+}
+
+impl<B: Clone> Pass<B> for Inline {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn run(&mut self, program: &mut Program<B>) -> usize {
+        program.inline()
+    }
+}
+
+impl<B: Flow + Clone + PartialEq> Pass<B> for Devirtualize {
+    fn name(&self) -> &'static str {
+        "devirtualize"
+    }
+
+    fn run(&mut self, program: &mut Program<B>) -> usize {
+        program.devirtualize(self.root, self.k)
+    }
+}
+
+impl<B: Clone> Pass<B> for EtaReduce {
+    fn name(&self) -> &'static str {
+        "eta-reduce"
+    }
+
+    fn run(&mut self, program: &mut Program<B>) -> usize {
+        program.eta_reduce()
+    }
+}
+
+impl<B: Fold + Clone> Pass<B> for ConstantFold {
+    fn name(&self) -> &'static str {
+        "constant-fold"
+    }
+
+    fn run(&mut self, program: &mut Program<B>) -> usize {
+        program.constant_fold(self.root)
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            "2" => Ok(Self::O2),
+            _ => Err(format!("Unknown optimisation level `{s}`.")),
+        }
+    }
+}
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<20} {:>10.2?} {:>6} changes, procedures {:>4} → {:<4} atoms {:>5} → {}",
+            self.pass,
+            self.duration,
+            self.changes,
+            self.procedures.0,
+            self.procedures.1,
+            self.atoms.0,
+            self.atoms.1
+        )
+    }
+}
+
+impl Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "IR verification failed after `{}`: {}",
+            self.pass, self.error
+        )
+    }
+}
+
+impl Error for PassError {}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::tests::program};
+
+    const SOURCE: &str = "f a ret: add a 1 ret\nmain exit: f 1 (x: exit x)\n";
+
+    #[test]
+    fn pipelines_verify() {
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let mut program = program(SOURCE);
+            let main = program.procedure_by_name("main").unwrap().id();
+            let mut passes = PassManager::with_level(main, level).verify(true);
+            passes.run(&mut program).unwrap();
+            assert_eq!(passes.statistics().last().unwrap().pass, "closure-analysis");
+        }
+    }
+
+    #[test]
+    fn closure_analysis_changes() {
+        let mut program = program(SOURCE);
+        assert_eq!(ClosureAnalysis.run(&mut program), 0);
+        for proc in &mut program.procedures {
+            proc.closure.clear();
+        }
+        // Only the continuation `x: exit x` captures `exit`.
+        assert_eq!(ClosureAnalysis.run(&mut program), 1);
+    }
+
+    #[test]
+    fn verification_fails() {
+        let mut program = program(SOURCE);
+        let main = program.procedure_by_name("main").unwrap().id();
+        program.procedures.retain(|p| p.id() == main);
+        let error = PassManager::new(main).verify(true).run(&mut program);
+        assert!(matches!(
+            error,
+            Err(PassError {
+                pass:  "input",
+                error: VerifyError::UnboundReference { .. },
+            })
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+//! Consistency checks on the intermediate representation.
+
+use {
+    super::{Atom, Program},
+    core::fmt::{self, Display},
+    std::error::Error,
+};
+
+/// A violated invariant of the intermediate representation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VerifyError {
+    /// An identifier is bound more than once.
+    DuplicateBinder { id: u32 },
+    /// A procedure has an empty body.
+    EmptyBody { procedure: u32 },
+    /// A reference to an identifier that is not bound anywhere.
+    UnboundReference { procedure: u32, id: u32 },
+    /// A procedure references a value that is not in scope.
+    NotInScope { procedure: u32, id: u32 },
+    /// A procedure that should be closed has a free variable.
+    FreeVariable { procedure: u32, id: u32 },
+    /// A procedure creates a closure without having the captured value in
+    /// scope.
+    MissingCapture {
+        procedure: u32,
+        closure:   u32,
+        id:        u32,
+    },
+}
+
+impl<B> Program<B> {
+    /// Check structural invariants that hold regardless of closure analysis.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let mut binders = self.identifiers().map(|i| i.id).collect::<Vec<_>>();
+        binders.sort_unstable();
+        if let Some(pair) = binders.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(VerifyError::DuplicateBinder { id: pair[0] });
+        }
+        for proc in &self.procedures {
+            if proc.body.is_empty() {
+                return Err(VerifyError::EmptyBody {
+                    procedure: proc.id(),
+                });
+            }
+            for atom in &proc.body {
+                let Atom::Reference { id, .. } = atom else {
+                    continue;
+                };
+                if binders.binary_search(id).is_err() {
+                    return Err(VerifyError::UnboundReference {
+                        procedure: proc.id(),
+                        id:        *id,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if closures are valid. Requires closures to be up to date.
+    pub fn closure_check(&self) -> Result<(), VerifyError> {
+        for proc in &self.procedures {
+            let contains = |id: u32| {
+                proc.arguments.iter().any(|arg| arg.id == id) || proc.closure.contains(&id)
+            };
+            for atom in &proc.body {
+                let Atom::Reference { id, .. } = atom else {
+                    continue;
+                };
+                if let Some(closure) = self.procedures.iter().find(|p| p.id() == *id) {
+                    for captured in &closure.closure {
+                        if !contains(*captured) {
+                            return Err(VerifyError::MissingCapture {
+                                procedure: proc.id(),
+                                closure:   *id,
+                                id:        *captured,
+                            });
+                        }
+                    }
+                } else if !contains(*id) {
+                    return Err(VerifyError::NotInScope {
+                        procedure: proc.id(),
+                        id:        *id,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<B: Clone> Program<B> {
+    /// Full verification: structural invariants, plus a fresh closure analysis
+    /// must be consistent and leave the `root` procedure without free
+    /// variables.
+    pub fn verify_scopes(&self, root: u32) -> Result<(), VerifyError> {
+        self.verify()?;
+        let mut program = self.clone();
+        program.closure_analysis();
+        program.closure_check()?;
+        if let Some(id) = program
+            .procedure_by_id(root)
+            .and_then(|p| p.closure.first())
+        {
+            return Err(VerifyError::FreeVariable {
+                procedure: root,
+                id:        *id,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DuplicateBinder { id } => write!(f, "identifier {id} is bound more than once"),
+            Self::EmptyBody { procedure } => write!(f, "procedure {procedure} has an empty body"),
+            Self::UnboundReference { procedure, id } => {
+                write!(
+                    f,
+                    "procedure {procedure} references unbound identifier {id}"
+                )
+            }
+            Self::NotInScope { procedure, id } => {
+                write!(
+                    f,
+                    "procedure {procedure} references {id} which is not in scope"
+                )
+            }
+            Self::FreeVariable { procedure, id } => {
+                write!(f, "procedure {procedure} has free variable {id}")
+            }
+            Self::MissingCapture {
+                procedure,
+                closure,
+                id,
+            } => write!(
+                f,
+                "procedure {procedure} creates closure {closure} but does not close over {id}"
+            ),
+        }
+    }
+}
+
+impl Error for VerifyError {}
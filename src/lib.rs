@@ -1,11 +1,16 @@
 #![doc = include_str!("../Readme.md")]
 #![doc(issue_tracker_base_url = "https://github.com/recmo/olus/issues/")]
 
+pub mod builtins;
 mod diagnostic;
+pub mod differential;
+pub mod engine;
 mod files;
 pub mod front;
 pub mod interpreter;
 pub mod ir;
+pub mod repl;
+pub mod types;
 
 pub use crate::{
     diagnostic::Diagnostic,
@@ -13,4 +18,28 @@ pub use crate::{
 };
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use crate::{
+        builtins::{Builtin, evaluate},
+        front::{compile, parse},
+        interpreter::{self, Value},
+        ir::Program,
+    };
+
+    /// Compile a program that is known to be valid, with closures analysed.
+    pub fn program(source: &str) -> Program<Builtin> {
+        let root = parse(source);
+        let mut program = compile(source.to_string(), &root, Builtin::resolve)
+            .unwrap_or_else(|errors| panic!("Does not compile: {errors:?}"));
+        program.closure_analysis();
+        program
+    }
+
+    /// Run `main exit` of a program and return its exit status.
+    pub fn status(source: &str) -> i32 {
+        let program = program(source);
+        let main = program.procedure_by_name("main").unwrap().id();
+        let call = [Value::Closure(main, vec![]), Value::Builtin(Builtin::Exit)];
+        interpreter::evaluate(&program, evaluate, &call)
+    }
+}
@@ -0,0 +1,320 @@
+//! Interactive sessions.
+//!
+//! Definitions entered in a [`Session`] accumulate into a program, replacing
+//! earlier definitions of the same name. Any other input is a statement, which
+//! is called with one more argument, a continuation that receives the result:
+//!
+//! ```text
+//! > double x ret: ret (mul x 2)
+//! > double 21
+//! 42
+//! ```
+//!
+//! Lines starting with `:` are commands, see [`Session::input`].
+
+use {
+    crate::{
+        FileId, Files,
+        builtins::{Builtin, Capabilities},
+        engine::{Engine, EngineError, Module},
+        front::{ElementRef, Kind, Node, NodeExt, parse},
+        interpreter::Value,
+        ir::{Procedure, Program, format_procedure},
+    },
+    core::fmt::{self, Display, Write},
+    std::error::Error,
+};
+
+/// Name of the procedure wrapping a statement and of its continuation. These
+/// are symbols nobody is expected to use.
+const STATEMENT: &str = "⁂";
+const RESULT: &str = "⁑";
+
+/// An interactive session.
+pub struct Session {
+    engine:      Engine,
+    /// The file holding the source of the last compiled input, which is
+    /// reused so the session does not grow.
+    file:        FileId,
+    /// Source of every top-level definition, with the names it defines.
+    definitions: Vec<(Vec<String>, String)>,
+    /// The compiled definitions.
+    module:      Option<Module>,
+    history:     Vec<String>,
+}
+
+/// Reasons an input can fail.
+#[derive(Debug)]
+pub enum SessionError {
+    /// The input did not compile or the statement failed.
+    Engine(EngineError),
+    /// The statement does not parse.
+    Syntax,
+    /// The command does not exist.
+    UnknownCommand { command: String },
+    /// There is no procedure of this name to show.
+    UnknownProcedure { name: String },
+}
+
+impl Session {
+    /// # Panics
+    ///
+    /// Panics if the empty program does not compile.
+    #[must_use]
+    pub fn new(capabilities: Capabilities) -> Self {
+        let mut engine = Engine::new().capabilities(capabilities);
+        let file = engine
+            .compile("<repl>", "")
+            .expect("The empty program compiles.")
+            .file();
+        Self {
+            engine,
+            file,
+            definitions: Vec::new(),
+            module: None,
+            history: Vec::new(),
+        }
+    }
+
+    /// Continue the history of an earlier session.
+    #[must_use]
+    pub fn with_history(mut self, history: Vec<String>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Sources of the session, for reporting diagnostics.
+    #[must_use]
+    pub const fn files(&self) -> &Files {
+        self.engine.files()
+    }
+
+    /// All inputs so far, oldest first.
+    #[must_use]
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Evaluate a complete input, see [`crate::front::is_complete`], and
+    /// return the output.
+    ///
+    /// The commands are `:ir name` to show the IR of a procedure and the
+    /// procedures inside it, `:closure name` to show their closures and
+    /// `:history` to show all inputs.
+    ///
+    /// # Errors
+    ///
+    /// Returns the diagnostics if the input does not compile, the error of
+    /// the statement or an invalid command.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a runtime error in a statement, see
+    /// [`Capabilities::evaluate`].
+    pub fn input(&mut self, input: &str) -> Result<String, SessionError> {
+        let input = input.trim_end();
+        self.history.push(input.to_string());
+        if let Some(command) = input.strip_prefix(':') {
+            return self.command(command);
+        }
+        // Statements end in a newline.
+        let source = format!("{input}\n");
+        let new = definitions(&parse(&source), &source);
+        if new.is_empty() {
+            self.statement(input)
+        } else {
+            self.define(new)
+        }
+    }
+
+    /// Add definitions, each replacing earlier ones of the same names.
+    fn define(&mut self, new: Vec<(Vec<String>, String)>) -> Result<String, SessionError> {
+        let mut definitions = self.definitions.clone();
+        for (names, source) in new {
+            definitions.retain(|(defined, _)| !defined.iter().any(|name| names.contains(name)));
+            definitions.push((names, source));
+        }
+        let module = compile(&mut self.engine, self.file, &definitions, None)?;
+        self.definitions = definitions;
+        self.module = Some(module);
+        Ok(String::new())
+    }
+
+    /// Evaluate a statement and show the values passed to its continuation.
+    fn statement(&mut self, input: &str) -> Result<String, SessionError> {
+        let module = compile(&mut self.engine, self.file, &self.definitions, Some(input))?;
+        // The parser reports syntax errors and leaves out what it can not parse.
+        let id = module
+            .program()
+            .procedure_by_name(STATEMENT)
+            .ok_or(SessionError::Syntax)?
+            .id();
+        let values = module.evaluate(vec![Value::Closure(id, vec![]), Value::Halt])?;
+        let mut output = String::new();
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                output.push(' ');
+            }
+            write!(output, "{value}").unwrap();
+        }
+        Ok(output)
+    }
+
+    fn command(&self, command: &str) -> Result<String, SessionError> {
+        let (command, name) = command.split_once(' ').unwrap_or((command, ""));
+        let name = name.trim();
+        match command {
+            "ir" => {
+                let (program, procedures) = self.procedures(name)?;
+                let mut output = String::new();
+                for procedure in procedures {
+                    output.push_str(&format_procedure(program, procedure));
+                }
+                Ok(output.trim_end().to_string())
+            }
+            "closure" => {
+                let (program, procedures) = self.procedures(name)?;
+                let name = |id| program.id_string(id).unwrap_or("_").to_string();
+                let mut output = String::new();
+                for procedure in procedures {
+                    let closure = procedure.closure.iter().map(|&id| name(id));
+                    let closure = closure.collect::<Vec<_>>().join(", ");
+                    writeln!(output, "{}: [{closure}]", name(procedure.id())).unwrap();
+                }
+                Ok(output.trim_end().to_string())
+            }
+            "history" => Ok(self.history.join("\n")),
+            _ => Err(SessionError::UnknownCommand {
+                command: command.to_string(),
+            }),
+        }
+    }
+
+    /// The procedure called `name` and the procedures inside it.
+    fn procedures(
+        &self,
+        name: &str,
+    ) -> Result<(&Program<Builtin>, Vec<&Procedure<Builtin>>), SessionError> {
+        let unknown = || SessionError::UnknownProcedure {
+            name: name.to_string(),
+        };
+        let program = self.module.as_ref().ok_or_else(unknown)?.program();
+        let outer = program.procedure_by_name(name).ok_or_else(unknown)?.source;
+        let procedures = program
+            .procedures
+            .iter()
+            .filter(|procedure| {
+                outer.start <= procedure.source.start && procedure.source.end <= outer.end
+            })
+            .collect();
+        Ok((program, procedures))
+    }
+}
+
+/// Compile the definitions and optionally a statement into `file`.
+fn compile(
+    engine: &mut Engine,
+    file: FileId,
+    definitions: &[(Vec<String>, String)],
+    statement: Option<&str>,
+) -> Result<Module, SessionError> {
+    let mut source = String::new();
+    for (_, definition) in definitions {
+        source.push_str(definition);
+        source.push('\n');
+    }
+    if let Some(statement) = statement {
+        writeln!(source, "{STATEMENT} {RESULT}: {statement} {RESULT}").unwrap();
+    }
+    Ok(engine.recompile(file, source)?)
+}
+
+/// Top-level procedures and data types with the names they define and their
+/// source, including the indented block that follows a procedure.
+fn definitions(root: &Node, source: &str) -> Vec<(Vec<String>, String)> {
+    let nodes = root.children().collect::<Vec<_>>();
+    let mut definitions = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        let mut names = Vec::new();
+        match node.kind() {
+            Kind::Proc => names.extend(name(node)),
+            Kind::Data => {
+                names.extend(name(node));
+                for constructor in node.children().filter(|n| n.kind() == Kind::Constructor) {
+                    names.extend(name(constructor));
+                }
+            }
+            _ => continue,
+        }
+        let end = nodes[i + 1..]
+            .iter()
+            .find(|node| node.kind() != Kind::Block)
+            .map_or(source.len(), |node| node.span().start);
+        let text = source[node.span().start..end].trim_end();
+        definitions.push((names, text.to_string()));
+    }
+    definitions
+}
+
+/// The first identifier of a node.
+fn name(node: &Node) -> Option<String> {
+    node.children_with_tokens()
+        .filter_map(ElementRef::into_token)
+        .find(|token| token.kind() == Kind::Identifier)
+        .map(|token| token.text().to_string())
+}
+
+impl From<EngineError> for SessionError {
+    fn from(error: EngineError) -> Self {
+        Self::Engine(error)
+    }
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Engine(error) => write!(f, "{error}"),
+            Self::Syntax => write!(f, "syntax error"),
+            Self::UnknownCommand { command } => write!(
+                f,
+                "unknown command `:{command}`, expected `:ir`, `:closure` or `:history`"
+            ),
+            Self::UnknownProcedure { name } => write!(f, "no procedure `{name}`"),
+        }
+    }
+}
+
+impl Error for SessionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redefine() {
+        let mut session = Session::new(Capabilities::default());
+        session
+            .input("double x ret: ret (mul x 2)\ntriple x ret: ret (mul x 3)")
+            .unwrap();
+        session.input("double x ret: ret (add x x)").unwrap();
+        assert_eq!(session.input("triple 2").unwrap(), "6");
+        assert_eq!(session.input("double 21").unwrap(), "42");
+        let file = session.module.as_ref().unwrap().file();
+        assert_eq!(file, session.file);
+    }
+
+    #[test]
+    fn commands() {
+        let mut session = Session::new(Capabilities::default());
+        session.input("double x ret: ret (mul x 2)").unwrap();
+        let ir = session.input(":ir double").unwrap();
+        assert!(ir.contains("double_0 x_1 ret_2: @mul x_1 2"), "{ir}");
+        let closure = session.input(":closure double").unwrap();
+        assert!(closure.contains("double: []"), "{closure}");
+        assert!(matches!(
+            session.input(":ir triple"),
+            Err(SessionError::UnknownProcedure { .. })
+        ));
+        assert_eq!(session.history().len(), 4);
+    }
+}
@@ -0,0 +1,829 @@
+//! Hindley–Milner style type inference.
+//!
+//! Procedures never return, so the main compound type is the procedure type
+//! `(T₁ … Tₙ)` of something that can be called with arguments of types `Tᵢ`.
+//! The others are arrays `[T]` and maps `{K: V}` of the builtin containers.
+//! Scott encoded data is naturally recursive, e.g. natural numbers have type
+//! `μa.(() (a))`, so types are graphs. The occurs check only rejects types
+//! that occur as a parameter of themselves, like `μa.(a)` from `f x: x x`.
+//!
+//! Procedures without closure are generalized, everything else is
+//! monomorphic.
+//!
+//! Values of declared data types are tagged with their type, which must agree
+//! on unification. Calling such a value is a match and must have exactly one
+//! case per constructor.
+//!
+//! Binders can be annotated with a type in the same notation as [`Type`]'s
+//! `Display`, e.g. `fact n[Number] ret[(Number)]: …`. Variables in an
+//! annotation are rigid: the inferred type must be at least as general.
+
+use {
+    crate::{
+        Diagnostic, FileId,
+        front::Span,
+        ir::{Atom, Identifier, Program},
+    },
+    core::{
+        fmt::{self, Display},
+        iter::Peekable,
+        str::{CharIndices, FromStr},
+    },
+    petgraph::algo::{condensation, toposort},
+    std::collections::{HashMap, HashSet},
+};
+
+/// A type, where `Variable`s are universally quantified.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Type {
+    Number,
+    String,
+    Variable(u32),
+    /// Something that can be called with the given argument types.
+    Procedure(Vec<Self>),
+    /// A recursive type `μa.T` where `Variable(a)` in `T` refers to the type
+    /// itself.
+    Recursive(u32, Box<Self>),
+    /// A declared data type.
+    Data(String),
+    /// An array with elements of a type.
+    Array(Box<Self>),
+    /// A map from keys to values.
+    Map(Box<Self>, Box<Self>),
+}
+
+/// Builtins with a type signature.
+pub trait Typed {
+    fn signature(&self) -> Type;
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TypeError {
+    pub source:  Span,
+    pub message: String,
+}
+
+/// The result of type inference.
+pub struct Types {
+    graph:  Graph,
+    nodes:  HashMap<u32, usize>,
+    errors: Vec<TypeError>,
+}
+
+/// Type graph with union-find.
+#[derive(Clone, Default)]
+struct Graph {
+    nodes:    Vec<Node>,
+    parent:   Vec<usize>,
+    /// Data types with the names and number of fields of their constructors.
+    data:     Vec<(String, Vec<(String, usize)>)>,
+    /// The variable and type of the last failed occurs check.
+    infinite: Option<(usize, usize)>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Node {
+    Variable,
+    Number,
+    String,
+    /// Procedure with parameters, optionally tagged as a data type.
+    Procedure(Vec<usize>, Option<usize>),
+    Array(usize),
+    Map(usize, usize),
+}
+
+struct Inference<'a, B> {
+    program: &'a Program<B>,
+    graph:   Graph,
+    /// Type of every identifier.
+    nodes:   HashMap<u32, usize>,
+    /// Generalized procedures.
+    schemes: HashMap<u32, usize>,
+    /// Annotations with their rigid variables.
+    rigid:   Vec<(Span, Type, Vec<usize>)>,
+    errors:  Vec<TypeError>,
+}
+
+/// Infer types for a program. Requires closures to be up to date.
+#[must_use]
+pub fn infer<B: Typed>(program: &Program<B>) -> Types {
+    let mut inference = Inference {
+        program,
+        graph: Graph::default(),
+        nodes: HashMap::new(),
+        schemes: HashMap::new(),
+        rigid: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    // Values of data types.
+    let mut tags = HashMap::new();
+    for (index, data) in program.data.iter().enumerate() {
+        let constructors = data
+            .constructors
+            .iter()
+            .map(|c| {
+                let name = program.id_string(c.id).unwrap_or_default().to_string();
+                tags.insert(c.value, index);
+                (name, c.fields)
+            })
+            .collect();
+        inference.graph.data.push((data.name.clone(), constructors));
+    }
+
+    // Process strongly connected components of procedures, callees first.
+    let graph = program.closure_graph();
+    let condensed = condensation(graph, true);
+    let order = toposort(&condensed, None).expect("Condensation is acyclic.");
+    for node in order.iter().rev() {
+        let component = condensed.node_weight(*node).unwrap();
+        for proc_index in component {
+            let proc = &program.procedures[*proc_index];
+            let parameters = proc.arguments[1..]
+                .iter()
+                .map(|arg| inference.node(arg.id))
+                .collect();
+            let tag = tags.get(&proc.id()).copied();
+            let node = inference.graph.add(Node::Procedure(parameters, tag));
+            let name = inference.node(proc.id());
+            inference.graph.unify(name, node);
+        }
+        for proc_index in component {
+            for binder in &program.procedures[*proc_index].arguments {
+                inference.check_annotation(binder);
+            }
+        }
+        for proc_index in component {
+            inference.check_call(*proc_index);
+        }
+        for proc_index in component {
+            let proc = &program.procedures[*proc_index];
+            if proc.closure.is_empty() {
+                inference
+                    .schemes
+                    .insert(proc.id(), inference.nodes[&proc.id()]);
+            }
+        }
+    }
+
+    inference.check_rigid();
+    Types {
+        graph:  inference.graph,
+        nodes:  inference.nodes,
+        errors: inference.errors,
+    }
+}
+
+impl Types {
+    /// The type of an identifier.
+    #[must_use]
+    pub fn of(&self, id: u32) -> Option<Type> {
+        let node = *self.nodes.get(&id)?;
+        Some(self.graph.clone().extract(node))
+    }
+
+    #[must_use]
+    pub fn errors(&self) -> &[TypeError] {
+        &self.errors
+    }
+}
+
+impl TypeError {
+    #[must_use]
+    pub fn diagnostic(&self, file: FileId) -> Diagnostic {
+        Diagnostic {
+            message: self.message.clone(),
+            span:    file.span(self.source.start..self.source.end),
+        }
+    }
+}
+
+impl<B: Typed> Inference<'_, B> {
+    /// Type node for an identifier.
+    fn node(&mut self, id: u32) -> usize {
+        if let Some(node) = self.nodes.get(&id) {
+            return *node;
+        }
+        let node = self.graph.add(Node::Variable);
+        self.nodes.insert(id, node);
+        node
+    }
+
+    fn atom(&mut self, atom: &Atom<B>) -> usize {
+        match atom {
+            Atom::Number { .. } => self.graph.add(Node::Number),
+            Atom::String { .. } => self.graph.add(Node::String),
+            Atom::Builtin { builtin, .. } => {
+                self.graph.insert(&builtin.signature(), &mut HashMap::new())
+            }
+            Atom::Reference { id, .. } => {
+                if let Some(scheme) = self.schemes.get(id) {
+                    self.graph.instantiate(*scheme)
+                } else {
+                    self.node(*id)
+                }
+            }
+        }
+    }
+
+    /// Check the call in the body of a procedure.
+    fn check_call(&mut self, proc_index: usize) {
+        let proc = &self.program.procedures[proc_index];
+        let types = proc
+            .body
+            .iter()
+            .map(|atom| self.atom(atom))
+            .collect::<Vec<_>>();
+        let head = self.graph.find(types[0]);
+        match self.graph.nodes[head].clone() {
+            Node::Variable => {
+                let node = self.graph.add(Node::Procedure(types[1..].to_vec(), None));
+                self.unify(proc.body[0].source(), head, node);
+            }
+            Node::Procedure(parameters, _) if parameters.len() == types.len() - 1 => {
+                for ((parameter, argument), atom) in
+                    parameters.iter().zip(&types[1..]).zip(&proc.body[1..])
+                {
+                    self.unify(atom.source(), *parameter, *argument);
+                }
+            }
+            Node::Procedure(_, Some(tag)) => {
+                let (name, constructors) = &self.graph.data[tag];
+                let message = if types.len() - 1 < constructors.len() {
+                    let missing = constructors[types.len() - 1..]
+                        .iter()
+                        .map(|(name, _)| format!("`{name}`"))
+                        .collect::<Vec<_>>();
+                    format!("Match on {name} is missing {}.", missing.join(", "))
+                } else {
+                    format!(
+                        "Match on {name} has {} cases but {name} has {} constructors.",
+                        types.len() - 1,
+                        constructors.len()
+                    )
+                };
+                self.error(proc.body[0].source(), message);
+            }
+            Node::Procedure(parameters, None) => {
+                let message = format!(
+                    "Procedure takes {} arguments but {} are given.",
+                    parameters.len(),
+                    types.len() - 1
+                );
+                self.error(proc.body[0].source(), message);
+            }
+            Node::Number | Node::String | Node::Array(_) | Node::Map(..) => {
+                let found = self.graph.extract(head);
+                self.error(proc.body[0].source(), format!("Can not call a {found}."));
+            }
+        }
+    }
+
+    /// Unify a binder with its annotation.
+    fn check_annotation(&mut self, binder: &Identifier) {
+        let Some(source) = binder.annotation else {
+            return;
+        };
+        let text = self.program.string(source);
+        let annotation = match text[1..text.len() - 1].parse::<Type>() {
+            Ok(annotation) => annotation,
+            Err(message) => return self.error(source, message),
+        };
+        if let Some(name) = annotation.unknown_data(&self.graph.data) {
+            return self.error(source, format!("Unknown type `{name}`."));
+        }
+        let node = self.node(binder.id);
+        let inferred = self.graph.extract(node);
+        let mut variables = HashMap::new();
+        let expected = self.graph.insert(&annotation, &mut variables);
+        if !self.graph.unify(node, expected) {
+            self.error(
+                source,
+                format!("Annotation {annotation} does not match inferred type {inferred}."),
+            );
+        }
+        self.rigid
+            .push((source, annotation, variables.into_values().collect()));
+    }
+
+    /// Check that distinct variables in annotations remained distinct
+    /// variables.
+    fn check_rigid(&mut self) {
+        for (source, annotation, variables) in std::mem::take(&mut self.rigid) {
+            let mut roots = variables
+                .iter()
+                .map(|v| self.graph.find(*v))
+                .collect::<Vec<_>>();
+            let general = roots.iter().all(|r| self.graph.nodes[*r] == Node::Variable);
+            roots.sort_unstable();
+            roots.dedup();
+            if !general || roots.len() != variables.len() {
+                self.error(
+                    source,
+                    format!("Annotation {annotation} is more general than the inferred type."),
+                );
+            }
+        }
+    }
+
+    /// Unify the type of an argument with the expected type, reporting a
+    /// mismatch at `source`.
+    fn unify(&mut self, source: Span, expected: usize, found: usize) {
+        self.graph.infinite = None;
+        if self.graph.unify(expected, found) {
+            return;
+        }
+        let message = if let Some((variable, ty)) = self.graph.infinite.take() {
+            let variable = self.graph.extract(variable);
+            let ty = self.graph.extract(ty);
+            format!("Infinite type {variable} = {ty}.")
+        } else {
+            let expected = self.graph.extract(expected);
+            let found = self.graph.extract(found);
+            format!("Expected {expected}, found {found}.")
+        };
+        self.error(source, message);
+    }
+
+    fn error(&mut self, source: Span, message: String) {
+        let error = TypeError { source, message };
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.parent.push(self.parent.len());
+        self.parent.len() - 1
+    }
+
+    fn find(&mut self, node: usize) -> usize {
+        let parent = self.parent[node];
+        if parent == node {
+            return node;
+        }
+        let root = self.find(parent);
+        self.parent[node] = root;
+        root
+    }
+
+    /// Unify two types. Nodes are merged before their children are unified,
+    /// which makes this terminate on recursive types.
+    fn unify(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return true;
+        }
+        match (self.nodes[a].clone(), self.nodes[b].clone()) {
+            (Node::Variable, _) | (_, Node::Variable) => {
+                let (variable, ty) = if self.nodes[a] == Node::Variable {
+                    (a, b)
+                } else {
+                    (b, a)
+                };
+                if self.occurs(variable, ty) {
+                    self.infinite = Some((variable, ty));
+                    return false;
+                }
+                self.parent[variable] = ty;
+            }
+            (Node::Number, Node::Number) | (Node::String, Node::String) => self.parent[a] = b,
+            (Node::Procedure(x, s), Node::Procedure(y, t))
+                if x.len() == y.len() && (s == t || s.is_none() || t.is_none()) =>
+            {
+                self.parent[a] = b;
+                self.nodes[b] = Node::Procedure(y.clone(), s.or(t));
+                // Unify all pairs, even after a failure, to find all conflicts.
+                return x
+                    .iter()
+                    .zip(&y)
+                    .fold(true, |ok, (x, y)| self.unify(*x, *y) && ok);
+            }
+            (Node::Array(x), Node::Array(y)) => {
+                self.parent[a] = b;
+                return self.unify(x, y);
+            }
+            (Node::Map(key, value), Node::Map(other_key, other_value)) => {
+                self.parent[a] = b;
+                let keys = self.unify(key, other_key);
+                return self.unify(value, other_value) && keys;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Whether `variable` occurs as a parameter of `ty`, at an odd depth of
+    /// procedure types. Binding it would make a type like `μa.(a)`, which only
+    /// self application can produce. Occurrences at an even depth, like in
+    /// `μa.(() (a))`, and inside data types are allowed.
+    fn occurs(&mut self, variable: usize, ty: usize) -> bool {
+        let mut stack = vec![(ty, false)];
+        let mut seen = HashSet::new();
+        while let Some((node, parameter)) = stack.pop() {
+            let node = self.find(node);
+            if node == variable {
+                if parameter {
+                    return true;
+                }
+                continue;
+            }
+            if !seen.insert((node, parameter)) {
+                continue;
+            }
+            match &self.nodes[node] {
+                Node::Procedure(children, None) => {
+                    stack.extend(children.iter().map(|c| (*c, !parameter)));
+                }
+                Node::Array(element) => stack.push((*element, parameter)),
+                Node::Map(key, value) => stack.extend([(*key, parameter), (*value, parameter)]),
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Copy the graph reachable from `node` with fresh variables.
+    fn instantiate(&mut self, node: usize) -> usize {
+        fn copy(graph: &mut Graph, node: usize, copies: &mut HashMap<usize, usize>) -> usize {
+            let node = graph.find(node);
+            if let Some(copy) = copies.get(&node) {
+                return *copy;
+            }
+            let new = graph.add(Node::Variable);
+            copies.insert(node, new);
+            graph.nodes[new] = match graph.nodes[node].clone() {
+                Node::Procedure(children, tag) => {
+                    let children = children.iter().map(|c| copy(graph, *c, copies)).collect();
+                    Node::Procedure(children, tag)
+                }
+                Node::Array(element) => Node::Array(copy(graph, element, copies)),
+                Node::Map(key, value) => {
+                    Node::Map(copy(graph, key, copies), copy(graph, value, copies))
+                }
+                other => other,
+            };
+            new
+        }
+        copy(self, node, &mut HashMap::new())
+    }
+
+    /// Add a type to the graph. Variables are looked up in, or added to,
+    /// `variables`.
+    fn insert(&mut self, ty: &Type, variables: &mut HashMap<u32, usize>) -> usize {
+        match ty {
+            Type::Number => self.add(Node::Number),
+            Type::String => self.add(Node::String),
+            Type::Variable(name) => *variables
+                .entry(*name)
+                .or_insert_with(|| self.add(Node::Variable)),
+            Type::Procedure(parameters) => {
+                let parameters = parameters
+                    .iter()
+                    .map(|p| self.insert(p, variables))
+                    .collect();
+                self.add(Node::Procedure(parameters, None))
+            }
+            Type::Data(name) => {
+                let Some(tag) = self.data.iter().position(|(n, _)| n == name) else {
+                    return self.add(Node::Variable);
+                };
+                let cases = self.data[tag]
+                    .1
+                    .clone()
+                    .into_iter()
+                    .map(|(_, fields)| {
+                        let fields = (0..fields).map(|_| self.add(Node::Variable)).collect();
+                        self.add(Node::Procedure(fields, None))
+                    })
+                    .collect();
+                self.add(Node::Procedure(cases, Some(tag)))
+            }
+            Type::Array(element) => {
+                let element = self.insert(element, variables);
+                self.add(Node::Array(element))
+            }
+            Type::Map(key, value) => {
+                let key = self.insert(key, variables);
+                let value = self.insert(value, variables);
+                self.add(Node::Map(key, value))
+            }
+            Type::Recursive(name, body) => {
+                let node = self.add(Node::Variable);
+                let shadowed = variables.insert(*name, node);
+                let body = self.insert(body, variables);
+                // Recursion in annotations is explicit, so skip the occurs check.
+                let body = self.find(body);
+                self.parent[node] = body;
+                if let Some(shadowed) = shadowed {
+                    variables.insert(*name, shadowed);
+                } else {
+                    variables.remove(name);
+                }
+                node
+            }
+        }
+    }
+
+    /// Convert a node to a type, introducing `Recursive` where the graph has
+    /// cycles.
+    fn extract(&mut self, node: usize) -> Type {
+        fn convert(
+            graph: &mut Graph,
+            node: usize,
+            names: &mut HashMap<usize, u32>,
+            stack: &mut Vec<usize>,
+            recursive: &mut HashSet<usize>,
+        ) -> Type {
+            let node = graph.find(node);
+            let mut name = |node| {
+                let next = u32::try_from(names.len()).unwrap();
+                *names.entry(node).or_insert(next)
+            };
+            if stack.contains(&node) {
+                recursive.insert(node);
+                return Type::Variable(name(node));
+            }
+            match graph.nodes[node].clone() {
+                Node::Variable => Type::Variable(name(node)),
+                Node::Number => Type::Number,
+                Node::String => Type::String,
+                Node::Procedure(_, Some(tag)) => Type::Data(graph.data[tag].0.clone()),
+                compound => {
+                    stack.push(node);
+                    let mut convert =
+                        |node| Box::new(convert(graph, node, names, stack, recursive));
+                    let ty = match compound {
+                        Node::Procedure(children, _) => {
+                            Type::Procedure(children.iter().map(|c| *convert(*c)).collect())
+                        }
+                        Node::Array(element) => Type::Array(convert(element)),
+                        Node::Map(key, value) => Type::Map(convert(key), convert(value)),
+                        _ => unreachable!(),
+                    };
+                    stack.pop();
+                    if recursive.remove(&node) {
+                        Type::Recursive(names[&node], Box::new(ty))
+                    } else {
+                        ty
+                    }
+                }
+            }
+        }
+        convert(
+            self,
+            node,
+            &mut HashMap::new(),
+            &mut Vec::new(),
+            &mut HashSet::new(),
+        )
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let variable = |f: &mut fmt::Formatter, name: u32| {
+            let letter = char::from(b'a' + u8::try_from(name % 26).unwrap());
+            if name < 26 {
+                write!(f, "{letter}")
+            } else {
+                write!(f, "{letter}{}", name / 26)
+            }
+        };
+        match self {
+            Self::Number => write!(f, "Number"),
+            Self::String => write!(f, "String"),
+            Self::Variable(name) => variable(f, *name),
+            Self::Procedure(parameters) => {
+                write!(f, "(")?;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{parameter}")?;
+                }
+                write!(f, ")")
+            }
+            Self::Recursive(name, body) => {
+                write!(f, "μ")?;
+                variable(f, *name)?;
+                write!(f, ".{body}")
+            }
+            Self::Data(name) => write!(f, "{name}"),
+            Self::Array(element) => write!(f, "[{element}]"),
+            Self::Map(key, value) => write!(f, "{{{key}: {value}}}"),
+        }
+    }
+}
+
+impl Type {
+    /// The first data type not among the declared ones.
+    fn unknown_data<T>(&self, data: &[(String, T)]) -> Option<&str> {
+        match self {
+            Self::Data(name) if !data.iter().any(|(n, _)| n == name) => Some(name),
+            Self::Procedure(parameters) => parameters.iter().find_map(|p| p.unknown_data(data)),
+            Self::Recursive(_, body) | Self::Array(body) => body.unknown_data(data),
+            Self::Map(key, value) => key.unknown_data(data).or_else(|| value.unknown_data(data)),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = String;
+
+    /// Parse a type in the notation used by `Display`. Variables are named by
+    /// lowercase identifiers, other capitalized names are data types and
+    /// `μa.T` is a recursive type.
+    #[allow(clippy::too_many_lines)]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        struct Parser<'a> {
+            source:    &'a str,
+            chars:     Peekable<CharIndices<'a>>,
+            variables: Vec<&'a str>,
+        }
+        impl<'a> Parser<'a> {
+            fn skip_whitespace(&mut self) {
+                while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            }
+
+            fn name(&mut self) -> Option<&'a str> {
+                let start = self.chars.peek()?.0;
+                let mut end = start;
+                while let Some((i, c)) = self
+                    .chars
+                    .next_if(|(_, c)| c.is_alphanumeric() || *c == '_')
+                {
+                    end = i + c.len_utf8();
+                }
+                (end > start).then(|| &self.source[start..end])
+            }
+
+            fn expect(&mut self, expected: char) -> Result<(), String> {
+                self.skip_whitespace();
+                match self.chars.next() {
+                    Some((_, c)) if c == expected => Ok(()),
+                    Some((_, c)) => Err(format!("Expected `{expected}`, found `{c}` in type.")),
+                    None => Err(format!("Expected `{expected}` at end of type.")),
+                }
+            }
+
+            fn variable(&mut self, name: &'a str) -> u32 {
+                let index = self
+                    .variables
+                    .iter()
+                    .rposition(|v| *v == name)
+                    .unwrap_or_else(|| {
+                        self.variables.push(name);
+                        self.variables.len() - 1
+                    });
+                u32::try_from(index).unwrap()
+            }
+
+            fn parse(&mut self) -> Result<Type, String> {
+                self.skip_whitespace();
+                match self.chars.peek().copied() {
+                    Some((_, '(')) => {
+                        self.chars.next();
+                        let mut parameters = vec![];
+                        loop {
+                            self.skip_whitespace();
+                            if self.chars.next_if(|(_, c)| *c == ')').is_some() {
+                                return Ok(Type::Procedure(parameters));
+                            }
+                            parameters.push(self.parse()?);
+                        }
+                    }
+                    Some((_, '[')) => {
+                        self.chars.next();
+                        let element = self.parse()?;
+                        self.expect(']')?;
+                        Ok(Type::Array(Box::new(element)))
+                    }
+                    Some((_, '{')) => {
+                        self.chars.next();
+                        let key = self.parse()?;
+                        self.expect(':')?;
+                        let value = self.parse()?;
+                        self.expect('}')?;
+                        Ok(Type::Map(Box::new(key), Box::new(value)))
+                    }
+                    Some((_, 'μ')) => {
+                        self.chars.next();
+                        let name = self.name().ok_or("Expected a variable after `μ`.")?;
+                        if self.chars.next_if(|(_, c)| *c == '.').is_none() {
+                            return Err(format!("Expected `.` after `μ{name}`."));
+                        }
+                        // Shadow any outer variable of the same name.
+                        let outer = self.variables.len();
+                        self.variables.push(name);
+                        let body = self.parse()?;
+                        self.variables[outer] = "";
+                        Ok(Type::Recursive(
+                            u32::try_from(outer).unwrap(),
+                            Box::new(body),
+                        ))
+                    }
+                    Some(_) => match self.name() {
+                        Some("Number") => Ok(Type::Number),
+                        Some("String") => Ok(Type::String),
+                        Some(name) if name.starts_with(char::is_lowercase) => {
+                            Ok(Type::Variable(self.variable(name)))
+                        }
+                        Some(name) => Ok(Type::Data(name.to_string())),
+                        None => Err(format!(
+                            "Unexpected `{}` in type.",
+                            self.chars.peek().unwrap().1
+                        )),
+                    },
+                    None => Err("Unexpected end of type.".to_string()),
+                }
+            }
+        }
+
+        let mut parser = Parser {
+            source:    s,
+            chars:     s.char_indices().peekable(),
+            variables: vec![],
+        };
+        let ty = parser.parse()?;
+        parser.skip_whitespace();
+        if let Some((_, c)) = parser.chars.peek() {
+            return Err(format!("Unexpected `{c}` after type."));
+        }
+        Ok(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::tests::program};
+
+    /// The type of `name` and the type errors of a program.
+    fn infer_source(source: &str, name: &str) -> (String, Vec<String>) {
+        let program = program(source);
+        let types = infer(&program);
+        let id = program.procedure_by_name(name).unwrap().id();
+        let errors = types.errors().iter().map(|e| e.message.clone()).collect();
+        (types.of(id).unwrap().to_string(), errors)
+    }
+
+    #[test]
+    fn builtins() {
+        let (ty, errors) = infer_source("f a ret: add a 1 ret\n", "f");
+        assert_eq!(ty, "(Number (Number))");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn generalization() {
+        let source = "id x ret: ret x\nf ret: id 1 (a: id “b” (b: ret a b))\n";
+        assert_eq!(infer_source(source, "id").0, "(a (a))");
+        assert_eq!(
+            infer_source(source, "f"),
+            ("((Number String))".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn recursive_types() {
+        let source = "zero z s: z\nsucc n z s: s n\nto_number n ret: n (: ret 0) (p: to_number p \
+                      (x: add x 1 ret))\n";
+        let (ty, errors) = infer_source(source, "to_number");
+        assert_eq!(ty, "(μa.(() (a)) (Number))");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn recursive_annotation() {
+        let source = "f n[μa.(() (a))] ret: n (: ret 0) (p: f p ret)\n";
+        let (ty, errors) = infer_source(source, "f");
+        assert_eq!(ty, "(μa.(() (a)) (Number))");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn occurs_check() {
+        let (_, errors) = infer_source("f x: x x\n", "f");
+        assert_eq!(errors, ["Infinite type a = (a)."]);
+        let (_, errors) = infer_source("f x: g x x\ng y z: z y\n", "f");
+        assert_eq!(errors, ["Infinite type a = (a)."]);
+    }
+
+    #[test]
+    fn mismatch() {
+        let (_, errors) = infer_source("f ret: add “a” 1 ret\n", "f");
+        assert_eq!(errors, ["Expected Number, found String."]);
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for ty in ["(Number (String))", "μa.(() (a))", "[{String: a}]", "Maybe"] {
+            assert_eq!(ty.parse::<Type>().unwrap().to_string(), ty);
+        }
+        assert!("(Number".parse::<Type>().is_err());
+    }
+}
@@ -0,0 +1,60 @@
+//! Differential tests of the optimisation passes, see [`olus::differential`].
+//! Every program with a `main` in `tests/golden` and random programs from
+//! [`Generator::runnable`] must run the same at every optimisation level. A
+//! program that does not is reduced to a minimal one.
+
+use {
+    olus::{
+        builtins::Builtin,
+        differential::{compare, reduce},
+        front::{Generator, compile, parse},
+        ir::OptLevel,
+    },
+    std::{fs, path::Path},
+};
+
+/// Number of random programs to check.
+const PROGRAMS: u64 = 500;
+
+const LEVELS: [OptLevel; 3] = [OptLevel::O0, OptLevel::O1, OptLevel::O2];
+
+#[test]
+fn golden_programs() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut paths = fs::read_dir(&directory)
+        .expect("The golden test directory exists.")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "olus")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        check(&path.display().to_string(), &source);
+    }
+}
+
+#[test]
+fn generated_programs() {
+    for seed in 0..PROGRAMS {
+        let source = Generator::new(seed).runnable();
+        let root = parse(&source);
+        assert!(
+            compile(source.clone(), &root, Builtin::resolve).is_ok(),
+            "Seed {seed} does not compile:\n{source}"
+        );
+        check(&format!("Seed {seed}"), &source);
+    }
+}
+
+fn check(name: &str, source: &str) {
+    for level in LEVELS {
+        if compare(source, level).is_some() {
+            let reduced = reduce(source, |source| compare(source, level).is_some());
+            let difference = compare(&reduced, level).unwrap();
+            panic!("{name} differs at {level:?}, reduced to:\n\n{reduced}\n{difference}");
+        }
+    }
+}
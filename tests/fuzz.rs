@@ -0,0 +1,71 @@
+//! Property tests of the front end. Random programs from [`Generator`] parse
+//! without errors, and any input, valid or not:
+//!
+//! * parses without panicking,
+//! * has a syntax tree whose text is the input,
+//! * compiles to a program or to diagnostics without panicking.
+//!
+//! The fuzz targets in `fuzz/` check the same on inputs found by a fuzzer.
+
+use olus::{
+    builtins::Builtin,
+    front::{ElementRef, Generator, Node, compile, parse},
+};
+
+/// Number of random programs to check.
+const PROGRAMS: u64 = 2000;
+
+#[test]
+fn generated_programs() {
+    for seed in 0..PROGRAMS {
+        let source = Generator::new(seed).program();
+        let root = check(&source);
+        assert!(!has_errors(&root), "Seed {seed} does not parse:\n{source}");
+    }
+}
+
+#[test]
+fn invalid_programs() {
+    for source in [
+        ")",
+        "f x: (g x\n",
+        "f x: “unterminated\n",
+        "f x[Number: x\n",
+        "f x:\n        g x\n    h x\n",
+        "data A = x | x\n",
+        "data A = x\ndata B = x\n",
+        "f: match x | A: y\n",
+        "f x: x\n\u{7}\n",
+        "f x:\ng y: y\n",
+    ] {
+        let root = check(source);
+        assert!(
+            has_errors(&root) || compile_fails(source, &root),
+            "{source:?} is valid"
+        );
+    }
+}
+
+/// Parse and compile the source and return the syntax tree.
+fn check(source: &str) -> Node {
+    let root = parse(source);
+    assert_eq!(
+        root.text().to_string(),
+        source,
+        "The syntax tree does not cover the source."
+    );
+    let _ = compile(source.to_string(), &root, Builtin::resolve);
+    root
+}
+
+fn compile_fails(source: &str, root: &Node) -> bool {
+    compile(source.to_string(), root, Builtin::resolve).is_err()
+}
+
+/// Whether the syntax tree has error tokens.
+fn has_errors(node: &Node) -> bool {
+    node.children_with_tokens().any(|element| match element {
+        ElementRef::Node(node) => has_errors(node),
+        ElementRef::Token(token) => token.kind().is_error(),
+    })
+}
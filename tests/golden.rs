@@ -0,0 +1,156 @@
+//! Golden tests. Every `.olus` file in `tests/golden` is parsed, compiled and
+//! run, and the results are compared to the expectation files next to it:
+//!
+//! * `.cst`: the concrete syntax tree.
+//! * `.ir`: the IR after closure analysis, before any optimisation.
+//! * `.diagnostics`: compile, type and arity errors, one per line.
+//! * `.stdout`: the output and exit status of `olus` without capabilities, only
+//!   for programs that compile and have a `main`.
+//! * `.tests`: the output and exit status of `olus test` without capabilities,
+//!   only for programs that compile and have tests.
+//!
+//! Missing and differing expectation files are failures. Run with
+//! `UPDATE_GOLDEN=1` to write them instead.
+
+use {
+    olus::{
+        Diagnostic, Files,
+        builtins::Builtin,
+        front::{compile, format_cst, parse},
+        ir::{ControlFlow, format_ir},
+        types::infer,
+    },
+    std::{
+        env,
+        fmt::Write,
+        fs,
+        path::Path,
+        process::{Command, Stdio},
+    },
+};
+
+#[test]
+fn golden() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut paths = fs::read_dir(&directory)
+        .expect("The golden test directory exists.")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "olus")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "No golden tests found.");
+
+    let mut failures = Vec::new();
+    for path in paths {
+        for (extension, actual) in outputs(&path) {
+            let expected_path = path.with_extension(extension);
+            match fs::read_to_string(&expected_path) {
+                Ok(expected) if expected == actual => {}
+                Ok(expected) if !update => {
+                    failures.push(difference(&expected_path, &expected, &actual));
+                }
+                Err(_) if !update => {
+                    failures.push(format!("{}: missing", expected_path.display()));
+                }
+                _ => {
+                    eprintln!("Writing {}", expected_path.display());
+                    fs::write(&expected_path, actual).unwrap();
+                }
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} golden files differ or are missing, run with UPDATE_GOLDEN=1 to update them:\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
+
+/// The outputs of a test, by extension of their expectation file.
+fn outputs(path: &Path) -> Vec<(&'static str, String)> {
+    let mut files = Files::new();
+    let file = files.insert(path.to_path_buf()).unwrap();
+    let source = files[file].contents();
+    let root = parse(source);
+    let mut outputs = vec![("cst", format_cst(&root))];
+    let diagnostics: Vec<Diagnostic> = match compile(source.to_string(), &root, Builtin::resolve) {
+        Ok(mut program) => {
+            program.closure_analysis();
+            outputs.push(("ir", format_ir(&program)));
+            let mut diagnostics = infer(&program)
+                .errors()
+                .iter()
+                .map(|error| error.diagnostic(file))
+                .collect::<Vec<_>>();
+            if let Some(main) = program.procedure_by_name("main") {
+                let flow = ControlFlow::analyse(&program, main.id(), 1);
+                diagnostics.extend(
+                    flow.arity_mismatches()
+                        .iter()
+                        .map(|mismatch| mismatch.diagnostic(file)),
+                );
+                outputs.push(("stdout", run(&[], path)));
+            }
+            if !program.tests.is_empty() {
+                outputs.push(("tests", run(&["test"], path)));
+            }
+            diagnostics
+        }
+        Err(errors) => errors.iter().map(|error| error.diagnostic(file)).collect(),
+    };
+    let mut text = String::new();
+    for diagnostic in diagnostics {
+        let range = diagnostic.span.range();
+        writeln!(
+            text,
+            "{}..{}: {}",
+            range.start, range.end, diagnostic.message
+        )
+        .unwrap();
+    }
+    outputs.push(("diagnostics", text));
+    outputs
+}
+
+/// Run `olus` on the program and return its output followed by its exit
+/// status.
+fn run(arguments: &[&str], path: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_olus"))
+        .args(arguments)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .expect("The olus binary runs.");
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    match output.status.code() {
+        Some(code) => writeln!(text, "exit status: {code}").unwrap(),
+        None => writeln!(text, "killed").unwrap(),
+    }
+    text
+}
+
+/// Describe the first line that differs.
+fn difference(path: &Path, expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(a), Some(b)) if a == b => {}
+            (None, None) => break,
+            (a, b) => {
+                return format!(
+                    "{}:{line}\n  expected: {}\n  actual:   {}",
+                    path.display(),
+                    a.unwrap_or("<end of file>"),
+                    b.unwrap_or("<end of file>")
+                );
+            }
+        }
+    }
+    format!("{}: differs in line endings", path.display())
+}